@@ -0,0 +1,323 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{fmt, io};
+
+use crate::discovery::DesktopFile;
+use crate::keyfile::{Group, KeyFile, KeyFileErr};
+use crate::squashfs::{SquashFs, SquashFsErr};
+use crate::{exec, xdg};
+
+const ICON_EXTENSIONS: &[&str] = &["png", "svg", "xpm"];
+
+#[derive(Debug)]
+pub enum AppImageErr {
+    NotAnAppImage,
+    MissingDesktopFile,
+    SquashFs(SquashFsErr),
+    KeyFile(KeyFileErr),
+    IO(io::Error),
+}
+
+impl fmt::Display for AppImageErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppImageErr::NotAnAppImage => write!(f, "not a type 2 AppImage"),
+            AppImageErr::MissingDesktopFile => write!(f, "no desktop file in the AppImage"),
+            AppImageErr::SquashFs(e) => write!(f, "{}", e),
+            AppImageErr::KeyFile(e) => write!(f, "{}", e),
+            AppImageErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for AppImageErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AppImageErr::SquashFs(e) => Some(e),
+            AppImageErr::KeyFile(e) => Some(e),
+            AppImageErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// `~/Applications`, where AppImages are usually kept.
+pub fn default_dirs() -> Vec<PathBuf> {
+    vec![shellexpand::tilde("~/Applications").into_owned().into()]
+}
+
+/// Where the desktop files & icons extracted from AppImages are kept.
+pub fn cache_dir() -> PathBuf {
+    xdg::cache_home().join("pyroxene").join("appimage")
+}
+
+/// Pass the desktop file of each AppImage in `dirs` to `on_file` as it is found. An AppImage is
+/// only extracted again if it changed since its desktop file was cached. What was extracted from
+/// AppImages that were deleted since is removed from the cache afterwards.
+pub fn desktop_files<F: FnMut(DesktopFile)>(dirs: &[PathBuf], mut on_file: F) {
+    let appimages = appimages(dirs);
+
+    for path in appimages.iter() {
+        if let Some(file) = desktop_file(path) {
+            on_file(file);
+        }
+    }
+
+    prune(dirs);
+}
+
+/// Remove the desktop files & icons of AppImages no longer in `dirs` from the cache. Nothing is
+/// removed while one of `dirs` can't be read, its AppImages may only be unavailable for now, e.g.
+/// on removable media that isn't mounted.
+pub fn prune(dirs: &[PathBuf]) {
+    if dirs.iter().any(|dir| dir.read_dir().is_err()) {
+        return;
+    }
+
+    let stems: Vec<String> = appimages(dirs)
+        .iter()
+        .map(|appimage| desktop_id(appimage).trim_end_matches(".desktop").to_owned())
+        .collect();
+
+    let entries = match cache_dir().read_dir() {
+        Ok(ok) => ok,
+        Err(_) => return,
+    };
+
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        // The icon is named like the desktop file, with its own extension.
+        let stem = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+
+        // Leave alone what wasn't extracted from an AppImage.
+        if stem.starts_with("appimage-") && !stems.iter().any(|known| known == stem) {
+            if let Err(e) = fs::remove_file(&path) {
+                println!(
+                    "Warning: Failed to remove cached AppImage file: {:?} ({})",
+                    path, e
+                );
+            }
+        }
+    }
+}
+
+/// The AppImages in `dirs`, their subdirectories aren't searched.
+pub fn appimages(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .filter_map(|dir| dir.read_dir().ok())
+        .flat_map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()))
+        .filter(|path| path.is_file() && is_appimage(path))
+        .collect()
+}
+
+/// The desktop file of an AppImage, extracted into the cache unless it is up to date. `None` if
+/// it can't be read.
+pub fn desktop_file(appimage: &Path) -> Option<DesktopFile> {
+    let id = desktop_id(appimage);
+
+    match cached_desktop_file(appimage, &id) {
+        Ok(cached) => {
+            Some(DesktopFile {
+                id,
+                path: cached,
+            })
+        },
+        Err(AppImageErr::SquashFs(e @ SquashFsErr::UnsupportedCompression(_))) => {
+            println!(
+                "Warning: Skipping AppImage with an unsupported compression: {:?} ({})",
+                appimage, e
+            );
+            None
+        },
+        Err(e) => {
+            println!("Warning: Failed to read AppImage: {:?} ({})", appimage, e);
+            None
+        },
+    }
+}
+
+/// AppImages are recognized by their `AI\x02` magic after the ELF identification.
+fn is_appimage(path: &Path) -> bool {
+    let mut ident = [0; 11];
+
+    File::open(path)
+        .and_then(|file| file.read_exact_at(&mut ident, 0))
+        .map(|_| &ident[..4] == b"\x7fELF" && &ident[8..] == b"AI\x02")
+        .unwrap_or(false)
+}
+
+/// `appimage-<name>.desktop`, using the lowercase file name without its extension.
+pub fn desktop_id(path: &Path) -> String {
+    let stem: String = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("appimage-{}.desktop", stem)
+}
+
+fn modified_secs(path: &Path) -> Result<u64, io::Error> {
+    Ok(path
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0))
+}
+
+/// The cached desktop file of an AppImage, extracting it first if the cache is missing or stale.
+fn cached_desktop_file(appimage: &Path, id: &str) -> Result<PathBuf, AppImageErr> {
+    let mtime = modified_secs(appimage).map_err(AppImageErr::IO)?;
+    let cached = cache_dir().join(id);
+
+    if let Ok(key_file) = KeyFile::open(&cached) {
+        if let Some(group) = key_file.group("Desktop Entry") {
+            if group.string("X-AppImage-Path").as_deref() == appimage.to_str()
+                && group.raw("X-AppImage-Mtime") == Some(mtime.to_string().as_str())
+            {
+                return Ok(cached);
+            }
+        }
+    }
+
+    extract(appimage, mtime, &cached)?;
+    Ok(cached)
+}
+
+/// The offset of the squashfs image, which follows the ELF runtime.
+fn elf_end(file: &File) -> Result<u64, AppImageErr> {
+    let mut header = [0; 64];
+    file.read_exact_at(&mut header, 0)
+        .map_err(AppImageErr::IO)?;
+
+    let u16_at = |pos: usize| u16::from_le_bytes([header[pos], header[pos + 1]]) as u64;
+    let u32_at = |pos: usize| {
+        u32::from_le_bytes([
+            header[pos],
+            header[pos + 1],
+            header[pos + 2],
+            header[pos + 3],
+        ]) as u64
+    };
+
+    // The section headers are at the end of the ELF file.
+    match header[4] {
+        1 => Ok(u32_at(0x20) + u16_at(0x2e) * u16_at(0x30)),
+        2 => Ok((u32_at(0x28) | u32_at(0x2c) << 32) + u16_at(0x3a) * u16_at(0x3c)),
+        _ => Err(AppImageErr::NotAnAppImage),
+    }
+}
+
+/// Extract the top-level desktop file & icon of an AppImage into the cache. `Exec` is pointed at
+/// the AppImage and the icon at the extracted copy.
+fn extract(appimage: &Path, mtime: u64, cached: &Path) -> Result<(), AppImageErr> {
+    let file = File::open(appimage).map_err(AppImageErr::IO)?;
+    let offset = elf_end(&file)?;
+    let image = SquashFs::open(file, offset).map_err(AppImageErr::SquashFs)?;
+    let root = image.root_entries().map_err(AppImageErr::SquashFs)?;
+
+    let desktop_name = root
+        .iter()
+        .find(|name| name.ends_with(".desktop"))
+        .ok_or(AppImageErr::MissingDesktopFile)?;
+    let text = image
+        .read_file(desktop_name)
+        .map_err(AppImageErr::SquashFs)?;
+    let mut key_file =
+        KeyFile::parse(&String::from_utf8_lossy(&text)).map_err(AppImageErr::KeyFile)?;
+
+    let appimage_arg = exec::quote_arg(&appimage.to_string_lossy().replace('%', "%%"));
+
+    for group in key_file.groups.iter_mut() {
+        if group.name == "Desktop Entry" || group.name.starts_with("Desktop Action ") {
+            point_exec_at(group, &appimage_arg);
+        }
+    }
+
+    let group = key_file.group_or_insert("Desktop Entry");
+    // It refers to a binary inside the AppImage.
+    group.retain(|e| e.key != "TryExec");
+
+    if let Some(icon) = extract_icon(&image, &root, group.string("Icon"), cached)? {
+        group.set_string("Icon", &icon.to_string_lossy());
+    }
+
+    group.set_string("X-AppImage-Path", &appimage.to_string_lossy());
+    group.set_raw("X-AppImage-Mtime", None, mtime.to_string());
+    key_file.save(cached).map_err(AppImageErr::KeyFile)
+}
+
+/// Replace the program of `Exec` with the AppImage, keeping the arguments.
+fn point_exec_at(group: &mut Group, appimage_arg: &str) {
+    let args = match group.string("Exec").map(|exec| exec::split_exec(&exec)) {
+        Some(Ok(args)) => args,
+        _ => return,
+    };
+
+    let mut exec = appimage_arg.to_owned();
+
+    for arg in args.iter().skip(1) {
+        exec.push(' ');
+        exec.push_str(&exec::quote_arg(arg));
+    }
+
+    group.set_string("Exec", &exec);
+}
+
+/// Extract the icon named by `Icon`, falling back to `.DirIcon`. Returns the path of the copy.
+fn extract_icon(
+    image: &SquashFs,
+    root: &[String],
+    icon: Option<String>,
+    cached: &Path,
+) -> Result<Option<PathBuf>, AppImageErr> {
+    let named = icon.and_then(|icon| {
+        ICON_EXTENSIONS
+            .iter()
+            .map(|ext| format!("{}.{}", icon, ext))
+            .find(|name| root.contains(name))
+    });
+
+    let (name, data) = match named {
+        Some(name) => {
+            let data = image.read_file(&name).map_err(AppImageErr::SquashFs)?;
+            (name, data)
+        },
+        None if root.iter().any(|name| name == ".DirIcon") => {
+            let data = image.read_file(".DirIcon").map_err(AppImageErr::SquashFs)?;
+            let extension = if data.starts_with(b"\x89PNG") {
+                "png"
+            } else {
+                "svg"
+            };
+            (format!(".DirIcon.{}", extension), data)
+        },
+        None => return Ok(None),
+    };
+
+    let extension = Path::new(&name)
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let path = cached.with_extension(extension);
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(AppImageErr::IO)?;
+    }
+
+    fs::write(&path, data).map_err(AppImageErr::IO)?;
+    Ok(Some(path))
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use flate2::read::GzDecoder;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+use crate::cache::{self, CatalogCache};
+use crate::locale::{Locale, LocalizedValue};
+use crate::xdg;
+
+/// An AppStream component, localized for the current locale.
+#[derive(Clone, Debug, Default)]
+pub struct Component {
+    pub id: String,
+    pub desktop_id: Option<String>,
+    pub name: Option<String>,
+    pub summary: Option<String>,
+    /// The description as plain text, paragraphs separated by a blank line.
+    pub description: Option<String>,
+    pub developer: Option<String>,
+    pub homepage: Option<String>,
+    pub keywords: Vec<String>,
+}
+
+/// The components of [`load`], shared by the entries. It is filled in once the metadata is
+/// parsed, until then entries have no component.
+#[derive(Clone, Debug, Default)]
+pub struct Catalog(Arc<OnceLock<HashMap<String, Component>>>);
+
+impl Catalog {
+    /// The component of a desktop-file ID, `None` if there is none or it isn't loaded yet.
+    pub fn get(&self, desktop_id: &str) -> Option<&Component> {
+        self.0.get()?.get(desktop_id)
+    }
+
+    /// Fill in the components. Only the first call has an effect.
+    pub fn set(&self, components: HashMap<String, Component>) {
+        let _ = self.0.set(components);
+    }
+}
+
+#[derive(Default)]
+struct ComponentBuilder {
+    id: String,
+    desktop_ids: Vec<String>,
+    name: LocalizedValue<String>,
+    summary: LocalizedValue<String>,
+    description: HashMap<Option<String>, Vec<String>>,
+    developer: LocalizedValue<String>,
+    homepage: Option<String>,
+    keywords: HashMap<Option<String>, Vec<String>>,
+}
+
+impl ComponentBuilder {
+    fn build(self, locale: Option<&Locale>) -> Component {
+        let best = |variants: HashMap<Option<String>, Vec<String>>| {
+            let mut value = LocalizedValue::default();
+
+            for (lang, items) in variants {
+                value.set(locale, lang.as_deref(), items);
+            }
+
+            value.into_best()
+        };
+
+        // Older metadata only names the component after its desktop file.
+        let desktop_id = match self.desktop_ids.first() {
+            Some(desktop_id) => Some(desktop_id.clone()),
+            None if self.id.ends_with(".desktop") => Some(self.id.clone()),
+            None if !self.id.is_empty() => Some(format!("{}.desktop", self.id)),
+            None => None,
+        };
+
+        Component {
+            id: self.id,
+            desktop_id,
+            name: self.name.into_best(),
+            summary: self.summary.into_best(),
+            description: best(self.description).map(|paragraphs| paragraphs.join("\n\n")),
+            developer: self.developer.into_best(),
+            homepage: self.homepage,
+            keywords: best(self.keywords).unwrap_or_default(),
+        }
+    }
+}
+
+/// The directories of installed metainfo files and of the system catalogs, most important first.
+pub fn metadata_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    for dir in xdg::data_dirs() {
+        dirs.push(dir.join("metainfo"));
+        dirs.push(dir.join("appdata"));
+    }
+
+    for dir in xdg::data_dirs()
+        .into_iter()
+        .chain(vec![PathBuf::from("/var/lib"), PathBuf::from("/var/cache")])
+    {
+        dirs.push(dir.join("swcatalog").join("xml"));
+        dirs.push(dir.join("app-info").join("xmls"));
+    }
+
+    dirs
+}
+
+/// Every component with a desktop-file ID, keyed by that ID. Installed metainfo files take
+/// precedence over the catalogs. Unchanged files are taken from the cache unless `no_cache`.
+pub fn load(no_cache: bool) -> HashMap<String, Component> {
+    let locale = Locale::from_env();
+    let mut cache = if no_cache {
+        CatalogCache::default()
+    } else {
+        CatalogCache::load()
+    };
+    let mut components = HashMap::new();
+
+    for dir in metadata_dirs() {
+        let mut paths: Vec<PathBuf> = match dir.read_dir() {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+
+        paths.sort();
+
+        for path in paths {
+            let name = path.to_string_lossy();
+
+            if !name.ends_with(".xml") && !name.ends_with(".xml.gz") {
+                continue;
+            }
+
+            match cache.components(&path, locale.as_ref()) {
+                Ok(parsed) => {
+                    for component in parsed {
+                        if let Some(desktop_id) = component.desktop_id.as_ref() {
+                            components
+                                .entry(desktop_id.clone())
+                                .or_insert_with(|| component.clone());
+                        }
+                    }
+                },
+                Err(e) => {
+                    println!(
+                        "Warning: Failed to parse AppStream file: {:?} ({})",
+                        path, e
+                    )
+                },
+            }
+        }
+    }
+
+    if !no_cache {
+        if let Err(e) = cache.save() {
+            println!(
+                "Warning: Failed to write cache: {:?} ({})",
+                cache::catalog_cache_path(),
+                e
+            );
+        }
+    }
+
+    components
+}
+
+/// Parse a metainfo file or a catalog, which may be gzip compressed.
+pub fn parse_file(path: &Path, locale: Option<&Locale>) -> Result<Vec<Component>, String> {
+    let file = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+
+    if path.extension().map(|e| e == "gz") == Some(true) {
+        parse(BufReader::new(GzDecoder::new(file)), locale)
+    } else {
+        parse(file, locale)
+    }
+}
+
+/// Parse the `<component>` elements of an XML document.
+pub fn parse<R: Read>(reader: R, locale: Option<&Locale>) -> Result<Vec<Component>, String> {
+    let parser = EventReader::new_with_config(
+        reader,
+        ParserConfig::new()
+            .trim_whitespace(false)
+            .cdata_to_characters(true),
+    );
+
+    let mut components = Vec::new();
+    let mut current: Option<ComponentBuilder> = None;
+    // Element names with their `xml:lang`, inherited from the parent if not set.
+    let mut stack: Vec<(String, Option<String>)> = Vec::new();
+    let mut text = String::new();
+    let mut desktop_launchable = false;
+    let mut homepage = false;
+
+    for event in parser {
+        match event.map_err(|e| e.to_string())? {
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                ..
+            } => {
+                let attribute = |key: &str, prefix: Option<&str>| {
+                    attributes
+                        .iter()
+                        .find(|a| a.name.local_name == key && a.name.prefix.as_deref() == prefix)
+                        .map(|a| a.value.clone())
+                };
+
+                let lang = attribute("lang", Some("xml"))
+                    .or_else(|| stack.last().and_then(|(_, lang)| lang.clone()));
+
+                match name.local_name.as_str() {
+                    "component" => current = Some(ComponentBuilder::default()),
+                    "launchable" => {
+                        desktop_launchable =
+                            attribute("type", None).as_deref() == Some("desktop-id")
+                    },
+                    "url" => homepage = attribute("type", None).as_deref() == Some("homepage"),
+                    _ => (),
+                }
+
+                // Inline markup is part of the surrounding paragraph.
+                if name.local_name != "em" && name.local_name != "code" {
+                    text.clear();
+                }
+
+                stack.push((name.local_name, lang));
+            },
+            XmlEvent::Characters(characters) | XmlEvent::Whitespace(characters) => {
+                text.push_str(&characters)
+            },
+            XmlEvent::EndElement {
+                ..
+            } => {
+                let (name, lang) = match stack.pop() {
+                    Some(some) => some,
+                    None => continue,
+                };
+
+                let parent = stack.last().map(|(parent, _)| parent.as_str());
+                let value = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+                if name == "component" {
+                    if let Some(builder) = current.take() {
+                        components.push(builder.build(locale));
+                    }
+
+                    continue;
+                }
+
+                let builder = match current.as_mut() {
+                    Some(some) => some,
+                    None => continue,
+                };
+
+                match (name.as_str(), parent) {
+                    ("id", Some("component")) => builder.id = value,
+                    ("name", Some("component")) => builder.name.set(locale, lang.as_deref(), value),
+                    ("summary", Some("component")) => {
+                        builder.summary.set(locale, lang.as_deref(), value)
+                    },
+                    ("developer_name", Some("component")) | ("name", Some("developer")) => {
+                        builder.developer.set(locale, lang.as_deref(), value)
+                    },
+                    ("p", _) | ("li", _) if stack.iter().any(|(n, _)| n == "description") => {
+                        let paragraph = if name == "li" {
+                            format!("• {}", value)
+                        } else {
+                            value
+                        };
+
+                        builder.description.entry(lang).or_default().push(paragraph);
+                    },
+                    ("keyword", Some("keywords")) => {
+                        builder.keywords.entry(lang).or_default().push(value)
+                    },
+                    ("launchable", _) if desktop_launchable => builder.desktop_ids.push(value),
+                    ("url", _) if homepage => builder.homepage = Some(value),
+                    _ => (),
+                }
+
+                // Text after inline markup still belongs to the paragraph.
+                if name != "em" && name != "code" {
+                    text.clear();
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(components)
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::desktop::{DesktopEntry, DesktopEntryErr, DesktopEntryKind};
+use crate::discovery::DesktopFile;
+use crate::{exec, xdg};
+
+/// `autostart` in `$XDG_CONFIG_HOME` & `$XDG_CONFIG_DIRS`, most important first.
+pub fn autostart_dirs() -> Vec<PathBuf> {
+    xdg::config_dirs()
+        .into_iter()
+        .map(|dir| dir.join("autostart"))
+        .collect()
+}
+
+/// The `.desktop` files of the autostart directories, sorted by name. A file shadows the files
+/// with the same name in less important directories, even if it's hidden itself. That is how a
+/// user disables a system-wide entry.
+pub fn desktop_files() -> Vec<DesktopFile> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+
+    for dir in autostart_dirs() {
+        let entries = match dir.read_dir() {
+            Ok(ok) => ok,
+            Err(_) => continue,
+        };
+
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if !path.is_file() || path.extension().map(|e| e == "desktop") != Some(true) {
+                continue;
+            }
+
+            let id = match path.file_name().and_then(|n| n.to_str()) {
+                Some(some) => some.to_owned(),
+                None => continue,
+            };
+
+            if seen.insert(id.clone()) {
+                files.push(DesktopFile {
+                    id,
+                    path,
+                });
+            }
+        }
+    }
+
+    files.sort_by(|a, b| a.id.cmp(&b.id));
+    files
+}
+
+/// Why an autostart entry isn't launched, `None` if it should be.
+fn skip_reason(result: &Result<DesktopEntry, DesktopEntryErr>) -> Option<String> {
+    match result {
+        Ok(entry) => {
+            if entry.kind == DesktopEntryKind::Directory {
+                Some(String::from("not an application"))
+            } else if entry.boolean("X-GNOME-Autostart-enabled") == Some(false) {
+                Some(String::from("disabled by X-GNOME-Autostart-enabled"))
+            } else {
+                None
+            }
+        },
+        Err(e) => {
+            match e {
+                DesktopEntryErr::UnsupportedType(_)
+                | DesktopEntryErr::Hidden
+                | DesktopEntryErr::OnlyShowIn
+                | DesktopEntryErr::NotShowIn
+                | DesktopEntryErr::TryExec(_) => Some(e.to_string()),
+                _ => None,
+            }
+        },
+    }
+}
+
+/// How long to wait before launching an entry, from `X-GNOME-Autostart-Delay` in seconds.
+fn delay(entry: &DesktopEntry) -> Duration {
+    entry
+        .string("X-GNOME-Autostart-Delay")
+        .and_then(|delay| delay.trim().parse::<f64>().ok())
+        .filter(|delay| delay.is_finite() && *delay > 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or_default()
+}
+
+/// Launch every autostart entry once, in the order of their delays. With `dry_run` the entries
+/// and their command lines are only listed. Returns `false` if any entry failed.
+pub fn run(desktops: &[String], dry_run: bool) -> bool {
+    let mut launches = Vec::new();
+    let mut ok = true;
+
+    for file in desktop_files() {
+        let result = DesktopEntry::with_id(file.id.clone(), &file.path, desktops);
+
+        if let Some(reason) = skip_reason(&result) {
+            if dry_run {
+                println!("Skip {}: {}", file.id, reason);
+            }
+
+            continue;
+        }
+
+        match result {
+            Ok(entry) => launches.push((delay(&entry), entry)),
+            Err(e) => {
+                println!(
+                    "Failed to parse desktop file: {}: {}",
+                    file.path.display(),
+                    e
+                );
+                ok = false;
+            },
+        }
+    }
+
+    // Stable, so entries with the same delay start in the order of their names.
+    launches.sort_by_key(|(delay, _)| *delay);
+    let start = Instant::now();
+
+    for (delay, entry) in launches {
+        if dry_run {
+            let command = match entry.exec.as_deref() {
+                Some(exec) => {
+                    match exec::expand(&entry, exec, &[]) {
+                        Ok(commands) => {
+                            commands
+                                .iter()
+                                .map(|args| {
+                                    args.iter()
+                                        .map(|arg| exec::quote_arg(arg))
+                                        .collect::<Vec<_>>()
+                                        .join(" ")
+                                })
+                                .collect::<Vec<_>>()
+                                .join("; ")
+                        },
+                        Err(e) => format!("invalid Exec ({})", e),
+                    }
+                },
+                None => entry.url.clone().unwrap_or_default(),
+            };
+
+            if delay.is_zero() {
+                println!("Launch {}: {}", entry.id, command);
+            } else {
+                println!(
+                    "Launch {} after {} s: {}",
+                    entry.id,
+                    delay.as_secs_f64(),
+                    command
+                );
+            }
+
+            continue;
+        }
+
+        if let Some(remaining) = delay.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        }
+
+        if let Err(e) = exec::launch(&entry, None, &[]) {
+            println!("Failed to launch {}: {}", entry.id, e);
+            ok = false;
+        }
+    }
+
+    ok
+}
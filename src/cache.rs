@@ -0,0 +1,599 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::{fs, io};
+
+use crate::appstream::{self, Catalog, Component};
+use crate::desktop::{
+    self, DesktopAction, DesktopEntry, DesktopEntryErr, DesktopEntryKind, Origin,
+};
+use crate::discovery::{self, DesktopFile};
+use crate::keyfile::KeyFile;
+use crate::locale::Locale;
+use crate::xdg;
+
+const MAGIC: &[u8] = b"PYROXENE";
+/// Bump whenever the layout changes, older caches are then discarded.
+const VERSION: u32 = 1;
+
+/// What parsing a desktop file resulted in, before the checks that depend on the environment.
+#[derive(Clone, Debug)]
+enum Record {
+    Entry(Box<DesktopEntry>),
+    Hidden,
+    UnsupportedType(String),
+}
+
+#[derive(Clone, Debug)]
+struct CachedFile {
+    id: String,
+    mtime: u64,
+    record: Record,
+}
+
+/// Parsed desktop entries together with the modification times of their files and of the
+/// application directories. Only files that changed since the last launch are parsed again and
+/// the directories are only scanned again if one of them changed. Entries can be parsed from
+/// multiple threads at once.
+#[derive(Debug, Default)]
+pub struct EntryCache {
+    /// The locale the entries were parsed for.
+    locale: String,
+    roots: Vec<PathBuf>,
+    /// The scanned directories including their subdirectories, 0 if missing.
+    dirs: Vec<(PathBuf, u64)>,
+    scanned: Vec<DesktopFile>,
+    files: Mutex<HashMap<PathBuf, CachedFile>>,
+    /// The files used during this launch, the only ones written back.
+    used: Mutex<HashMap<PathBuf, CachedFile>>,
+    changed: AtomicBool,
+}
+
+/// `$XDG_CACHE_HOME/pyroxene/entries`.
+pub fn cache_path() -> PathBuf {
+    xdg::cache_home().join("pyroxene").join("entries")
+}
+
+fn locale_key() -> String {
+    Locale::from_env().map(|l| l.key()).unwrap_or_default()
+}
+
+/// Read a cache file with `read`, `None` if it is missing or outdated.
+fn read_cache<T, F: FnOnce(&mut Reader) -> Option<T>>(path: &Path, read: F) -> Option<T> {
+    let data = fs::read(path).ok()?;
+
+    let mut reader = Reader {
+        data: &data,
+        pos: 0,
+    };
+
+    if reader.bytes(MAGIC.len()) != Some(MAGIC) || reader.u32() != Some(VERSION) {
+        return None;
+    }
+
+    let read = read(&mut reader);
+
+    if read.is_none() {
+        println!("Warning: Ignoring invalid cache: {:?}", path);
+    }
+
+    read
+}
+
+/// Replace a cache file with the data of `writer`.
+fn write_cache(path: &Path, writer: Writer) -> Result<(), io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, writer.data)?;
+    fs::rename(tmp_path, path)
+}
+
+impl EntryCache {
+    /// Read the cache, an empty one if it is missing, outdated or for another locale.
+    pub fn load() -> Self {
+        match read_cache(&cache_path(), |reader| reader.cache()) {
+            Some(cache) if cache.locale == locale_key() => cache,
+            _ => Self::default(),
+        }
+    }
+
+    /// The same files as [`discovery::desktop_files`], scanning the application directories
+    /// only if they changed.
+    pub fn desktop_files(&mut self) -> Vec<DesktopFile> {
+        let roots = discovery::application_dirs();
+
+        if !self.dirs.is_empty()
+            && self.roots == roots
+            && self.dirs.iter().all(|(dir, cached)| desktop::mtime(dir) == *cached)
+        {
+            return self.scanned.clone();
+        }
+
+        // Recorded before scanning so changes made during the scan are noticed next time.
+        self.dirs = roots
+            .iter()
+            .flat_map(|root| discovery::walk_dirs(root))
+            .map(|dir| {
+                let mtime = desktop::mtime(&dir);
+                (dir, mtime)
+            })
+            .collect();
+        self.roots = roots;
+        self.scanned = discovery::desktop_files();
+        self.changed.store(true, Ordering::Relaxed);
+        self.scanned.clone()
+    }
+
+    /// Parse a desktop file unless it is cached & unchanged, then check it against the
+    /// environment like [`DesktopEntry::with_id`] does.
+    pub fn entry(
+        &self,
+        file: &DesktopFile,
+        desktops: &[String],
+    ) -> Result<DesktopEntry, DesktopEntryErr> {
+        let mtime = desktop::mtime(&file.path);
+
+        let cached = self.files.lock().unwrap().remove(&file.path);
+
+        let cached = match cached {
+            Some(cached) if cached.mtime == mtime && cached.id == file.id => cached,
+            _ => {
+                self.changed.store(true, Ordering::Relaxed);
+
+                let key_file = KeyFile::open(&file.path)?;
+                let record = match DesktopEntry::parse(file.id.clone(), file.path.clone(), key_file)
+                {
+                    Ok(entry) => Record::Entry(Box::new(entry)),
+                    Err(DesktopEntryErr::Hidden) => Record::Hidden,
+                    Err(DesktopEntryErr::UnsupportedType(ty)) => Record::UnsupportedType(ty),
+                    // Errors are reported again on every launch until the file is fixed.
+                    Err(e) => return Err(e),
+                };
+
+                CachedFile {
+                    id: file.id.clone(),
+                    mtime,
+                    record,
+                }
+            },
+        };
+
+        let result = match &cached.record {
+            Record::Entry(entry) => {
+                let mut entry = entry.as_ref().clone();
+                entry.modified = cached.mtime;
+                entry.check(desktops)
+            },
+            Record::Hidden => Err(DesktopEntryErr::Hidden),
+            Record::UnsupportedType(ty) => Err(DesktopEntryErr::UnsupportedType(ty.clone())),
+        };
+
+        self.used.lock().unwrap().insert(file.path.clone(), cached);
+        result
+    }
+
+    /// Write the cache if anything changed. Files that weren't used during this launch are
+    /// dropped.
+    pub fn save(&self) -> Result<(), io::Error> {
+        let used = self.used.lock().unwrap();
+
+        if !self.changed.load(Ordering::Relaxed) && self.files.lock().unwrap().is_empty() {
+            return Ok(());
+        }
+
+        let mut writer = Writer::default();
+        writer.bytes(MAGIC);
+        writer.u32(VERSION);
+        writer.str(&locale_key());
+        writer.paths(&self.roots);
+        writer.u32(self.dirs.len() as u32);
+
+        for (dir, mtime) in self.dirs.iter() {
+            writer.path(dir);
+            writer.u64(*mtime);
+        }
+
+        writer.u32(self.scanned.len() as u32);
+
+        for file in self.scanned.iter() {
+            writer.str(&file.id);
+            writer.path(&file.path);
+        }
+
+        writer.u32(used.len() as u32);
+
+        for (path, cached) in used.iter() {
+            writer.path(path);
+            writer.str(&cached.id);
+            writer.u64(cached.mtime);
+
+            match &cached.record {
+                Record::Entry(entry) => {
+                    writer.u8(0);
+                    writer.entry(entry);
+                },
+                Record::Hidden => writer.u8(1),
+                Record::UnsupportedType(ty) => {
+                    writer.u8(2);
+                    writer.str(ty);
+                },
+            }
+        }
+
+        write_cache(&cache_path(), writer)
+    }
+}
+
+/// Parsed AppStream files together with their modification times, only files that changed since
+/// the last launch are parsed again, see [`appstream::load`].
+#[derive(Debug, Default)]
+pub struct CatalogCache {
+    /// The locale the components were localized for.
+    locale: String,
+    files: HashMap<PathBuf, (u64, Vec<Component>)>,
+    /// The files read during this launch, the only ones written back.
+    used: HashMap<PathBuf, (u64, Vec<Component>)>,
+    changed: bool,
+}
+
+/// `$XDG_CACHE_HOME/pyroxene/appstream`.
+pub fn catalog_cache_path() -> PathBuf {
+    xdg::cache_home().join("pyroxene").join("appstream")
+}
+
+impl CatalogCache {
+    /// Read the cache, an empty one if it is missing, outdated or for another locale.
+    pub fn load() -> Self {
+        match read_cache(&catalog_cache_path(), |reader| reader.catalog_cache()) {
+            Some(cache) if cache.locale == locale_key() => cache,
+            _ => Self::default(),
+        }
+    }
+
+    /// The components of a metainfo file or a catalog, parsed unless it is cached & unchanged.
+    pub fn components(
+        &mut self,
+        path: &Path,
+        locale: Option<&Locale>,
+    ) -> Result<&[Component], String> {
+        let mtime = desktop::mtime(path);
+
+        let components = match self.files.remove(path) {
+            Some((cached, components)) if cached == mtime => components,
+            _ => {
+                self.changed = true;
+                appstream::parse_file(path, locale)?
+            },
+        };
+
+        let (_, components) = self
+            .used
+            .entry(path.to_path_buf())
+            .or_insert((mtime, components));
+        Ok(components)
+    }
+
+    /// Write the cache if anything changed. Files that weren't read during this launch are
+    /// dropped.
+    pub fn save(&self) -> Result<(), io::Error> {
+        if !self.changed && self.files.is_empty() {
+            return Ok(());
+        }
+
+        let mut writer = Writer::default();
+        writer.bytes(MAGIC);
+        writer.u32(VERSION);
+        writer.str(&locale_key());
+        writer.u32(self.used.len() as u32);
+
+        for (path, (mtime, components)) in self.used.iter() {
+            writer.path(path);
+            writer.u64(*mtime);
+            writer.u32(components.len() as u32);
+            components
+                .iter()
+                .for_each(|component| writer.component(component));
+        }
+
+        write_cache(&catalog_cache_path(), writer)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes(value.as_bytes());
+    }
+
+    fn path(&mut self, path: &Path) {
+        self.str(&path.to_string_lossy());
+    }
+
+    fn paths(&mut self, paths: &[PathBuf]) {
+        self.u32(paths.len() as u32);
+        paths.iter().for_each(|path| self.path(path));
+    }
+
+    fn option(&mut self, value: &Option<String>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.str(value);
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn list(&mut self, values: &[String]) {
+        self.u32(values.len() as u32);
+        values.iter().for_each(|value| self.str(value));
+    }
+
+    fn entry(&mut self, entry: &DesktopEntry) {
+        self.u8(match entry.kind {
+            DesktopEntryKind::Application => 0,
+            DesktopEntryKind::Link => 1,
+            DesktopEntryKind::Directory => 2,
+        });
+        self.str(&entry.id);
+        self.path(&entry.source);
+        self.str(entry.origin.label());
+        self.str(&entry.name);
+        self.str(&entry.untranslated_name);
+        self.option(&entry.icon);
+        self.option(&entry.generic_name);
+        self.option(&entry.comment);
+        self.option(&entry.exec);
+        self.option(&entry.url);
+        self.option(&entry.path);
+        self.bool(entry.terminal);
+        self.list(&entry.categories);
+        self.list(&entry.keywords);
+        self.list(&entry.mime_types);
+        self.u32(entry.actions.len() as u32);
+
+        for action in entry.actions.iter() {
+            self.str(&action.id);
+            self.str(&action.name);
+            self.str(&action.untranslated_name);
+            self.option(&action.icon);
+            self.str(&action.exec);
+        }
+
+        self.option(&entry.try_exec);
+        self.bool(entry.no_display);
+        self.list(&entry.only_show_in);
+        self.list(&entry.not_show_in);
+        self.option(&entry.startup_wm_class);
+        self.option(&entry.flatpak_id);
+    }
+
+    fn component(&mut self, component: &Component) {
+        self.str(&component.id);
+        self.option(&component.desktop_id);
+        self.option(&component.name);
+        self.option(&component.summary);
+        self.option(&component.description);
+        self.option(&component.developer);
+        self.option(&component.homepage);
+        self.list(&component.keywords);
+    }
+}
+
+/// Reads what [`Writer`] wrote. Every method returns `None` if the data is truncated or invalid.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..(self.pos.checked_add(len)?))?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(self.u32()? as u64 | (self.u32()? as u64) << 32)
+    }
+
+    fn bool(&mut self) -> Option<bool> {
+        Some(self.u8()? != 0)
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).ok()
+    }
+
+    fn path(&mut self) -> Option<PathBuf> {
+        Some(self.str()?.into())
+    }
+
+    fn paths(&mut self) -> Option<Vec<PathBuf>> {
+        (0..self.u32()?).map(|_| self.path()).collect()
+    }
+
+    fn option(&mut self) -> Option<Option<String>> {
+        match self.u8()? {
+            0 => Some(None),
+            _ => Some(Some(self.str()?)),
+        }
+    }
+
+    fn list(&mut self) -> Option<Vec<String>> {
+        (0..self.u32()?).map(|_| self.str()).collect()
+    }
+
+    /// The document isn't cached, it's read from the file if needed, see
+    /// [`DesktopEntry::document`].
+    fn entry(&mut self) -> Option<DesktopEntry> {
+        let kind = match self.u8()? {
+            0 => DesktopEntryKind::Application,
+            1 => DesktopEntryKind::Link,
+            2 => DesktopEntryKind::Directory,
+            _ => return None,
+        };
+
+        Some(DesktopEntry {
+            kind,
+            id: self.str()?,
+            source: self.path()?,
+            // Taken from the cached file.
+            modified: 0,
+            origin: Origin::parse(&self.str()?)?,
+            name: self.str()?,
+            untranslated_name: self.str()?,
+            icon: self.option()?,
+            generic_name: self.option()?,
+            comment: self.option()?,
+            exec: self.option()?,
+            url: self.option()?,
+            path: self.option()?,
+            terminal: self.bool()?,
+            categories: self.list()?,
+            keywords: self.list()?,
+            mime_types: self.list()?,
+            actions: (0..self.u32()?)
+                .map(|_| {
+                    Some(DesktopAction {
+                        id: self.str()?,
+                        name: self.str()?,
+                        untranslated_name: self.str()?,
+                        icon: self.option()?,
+                        exec: self.str()?,
+                    })
+                })
+                .collect::<Option<_>>()?,
+            try_exec: self.option()?,
+            try_exec_failed: false,
+            no_display: self.bool()?,
+            only_show_in: self.list()?,
+            not_show_in: self.list()?,
+            startup_wm_class: self.option()?,
+            flatpak_id: self.option()?,
+            document: OnceLock::new(),
+            appstream: Catalog::default(),
+        })
+    }
+
+    fn cache(&mut self) -> Option<EntryCache> {
+        let locale = self.str()?;
+        let roots = self.paths()?;
+        let dirs = (0..self.u32()?)
+            .map(|_| Some((self.path()?, self.u64()?)))
+            .collect::<Option<_>>()?;
+        let scanned = (0..self.u32()?)
+            .map(|_| {
+                Some(DesktopFile {
+                    id: self.str()?,
+                    path: self.path()?,
+                })
+            })
+            .collect::<Option<_>>()?;
+        let mut files = HashMap::new();
+
+        for _ in 0..self.u32()? {
+            let path = self.path()?;
+            let id = self.str()?;
+            let mtime = self.u64()?;
+            let record = match self.u8()? {
+                0 => Record::Entry(Box::new(self.entry()?)),
+                1 => Record::Hidden,
+                2 => Record::UnsupportedType(self.str()?),
+                _ => return None,
+            };
+
+            files.insert(
+                path,
+                CachedFile {
+                    id,
+                    mtime,
+                    record,
+                },
+            );
+        }
+
+        Some(EntryCache {
+            locale,
+            roots,
+            dirs,
+            scanned,
+            files: Mutex::new(files),
+            used: Mutex::new(HashMap::new()),
+            changed: AtomicBool::new(false),
+        })
+    }
+
+    fn component(&mut self) -> Option<Component> {
+        Some(Component {
+            id: self.str()?,
+            desktop_id: self.option()?,
+            name: self.option()?,
+            summary: self.option()?,
+            description: self.option()?,
+            developer: self.option()?,
+            homepage: self.option()?,
+            keywords: self.list()?,
+        })
+    }
+
+    fn catalog_cache(&mut self) -> Option<CatalogCache> {
+        let locale = self.str()?;
+        let mut files = HashMap::new();
+
+        for _ in 0..self.u32()? {
+            let path = self.path()?;
+            let mtime = self.u64()?;
+            let components = (0..self.u32()?)
+                .map(|_| self.component())
+                .collect::<Option<_>>()?;
+            files.insert(path, (mtime, components));
+        }
+
+        Some(CatalogCache {
+            locale,
+            files,
+            used: HashMap::new(),
+            changed: false,
+        })
+    }
+}
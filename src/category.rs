@@ -1,40 +1,626 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
 use crate::desktop::DesktopEntry;
+use crate::directory::Directory;
 
-#[derive(Debug)]
+/// Which entries belong in a category, like the rules of `<Include>` & `<Exclude>` in a menu
+/// file.
+#[derive(Clone, Debug)]
+pub enum Rule {
+    All,
+    /// A desktop-file ID.
+    Filename(String),
+    /// A value of `Categories`, compared case-insensitively.
+    Category(String),
+    /// A main category of the entry, including the one implied by its additional categories.
+    MainCategory(String),
+    And(Vec<Rule>),
+    /// An empty `Or` matches nothing.
+    Or(Vec<Rule>),
+    Not(Box<Rule>),
+}
+
+impl Rule {
+    pub fn matches(&self, entry: &DesktopEntry) -> bool {
+        match self {
+            Rule::All => true,
+            Rule::Filename(id) => entry.id == *id,
+            Rule::Category(category) => {
+                entry
+                    .categories
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(category))
+            },
+            Rule::MainCategory(category) => {
+                main_categories(&entry.categories)
+                    .iter()
+                    .any(|c| c.eq_ignore_ascii_case(category))
+            },
+            Rule::And(rules) => rules.iter().all(|rule| rule.matches(entry)),
+            Rule::Or(rules) => rules.iter().any(|rule| rule.matches(entry)),
+            Rule::Not(rule) => !rule.matches(entry),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct Category {
     pub iden: String,
     pub name: String,
+    pub comment: Option<String>,
     pub icon: String,
+    pub rule: Rule,
+    /// Only take the entries no other category took, like `<OnlyUnallocated>`.
+    pub only_unallocated: bool,
+    /// Desktop-file IDs listed first, in this order. The other entries follow sorted by name.
+    pub layout: Vec<String>,
     pub entries: Vec<Arc<DesktopEntry>>,
+    /// Sub-categories, taking their entries from this category's.
+    pub children: Vec<Category>,
 }
 
 impl Category {
+    /// A category of the entries listing `iden` in their `Categories`.
     pub fn new<Id: Into<String>, N: Into<String>, Ic: Into<String>>(
         iden: Id,
         name: N,
         icon: Ic,
     ) -> Self {
+        let iden = iden.into();
+
         Category {
-            iden: iden.into(),
+            rule: Rule::Category(iden.clone()),
+            iden,
             name: name.into(),
+            comment: None,
             icon: icon.into(),
+            only_unallocated: false,
+            layout: Vec::new(),
             entries: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// A category of the entries belonging to the main category `iden`, with a sub-category for
+    /// each additional category that belongs to it, e.g. `IDE` & `Debugger` for `Development`.
+    pub fn main_category<Id: Into<String>, N: Into<String>, Ic: Into<String>>(
+        iden: Id,
+        name: N,
+        icon: Ic,
+    ) -> Self {
+        let mut category = Category::new(iden, name, icon);
+        category.rule = Rule::MainCategory(category.iden.clone());
+
+        for (additional, related) in ADDITIONAL_CATEGORIES.iter() {
+            let main = match related.first() {
+                Some(main) if *main == "Audio" || *main == "Video" => "AudioVideo",
+                Some(main) => main,
+                None => continue,
+            };
+
+            if main.eq_ignore_ascii_case(&category.iden) {
+                let mut child = Category::new(
+                    format!("{}/{}", category.iden, additional.to_lowercase()),
+                    display_name(additional),
+                    category.icon.clone(),
+                );
+
+                child.rule = Rule::Category(additional.to_string());
+                category.children.push(child);
+            }
+        }
+
+        category.children.sort_by_key(|c| c.name.to_lowercase());
+        category
+    }
+
+    /// A category of the entries no other category took, like a menu with `<OnlyUnallocated>` &
+    /// `<All>`.
+    pub fn unallocated<Id: Into<String>, N: Into<String>, Ic: Into<String>>(
+        iden: Id,
+        name: N,
+        icon: Ic,
+    ) -> Self {
+        let mut category = Category::new(iden, name, icon);
+        category.rule = Rule::All;
+        category.only_unallocated = true;
+        category
+    }
+
+    /// Use the name, comment & icon of a `.directory` file, those it sets.
+    pub fn apply_directory(&mut self, directory: &Directory) {
+        if let Some(name) = directory.name.as_ref() {
+            self.name = name.clone();
+        }
+
+        if let Some(comment) = directory.comment.as_ref() {
+            self.comment = Some(comment.clone());
+        }
+
+        if let Some(icon) = directory.icon.as_ref() {
+            self.icon = icon.clone();
+        }
+    }
+
+    /// The first pass of [`distribute`]. Returns the entries this category took, those of an
+    /// `only_unallocated` one are only the ones its sub-categories took.
+    fn allocate(
+        &mut self,
+        entries: &[Arc<DesktopEntry>],
+        allocated: &mut HashSet<*const DesktopEntry>,
+    ) -> Vec<Arc<DesktopEntry>> {
+        let matching: Vec<Arc<DesktopEntry>> = entries
+            .iter()
+            .filter(|entry| self.rule.matches(entry))
+            .cloned()
+            .collect();
+
+        let taken: HashSet<*const DesktopEntry> = self
+            .children
+            .iter_mut()
+            .flat_map(|child| child.allocate(&matching, allocated))
+            .map(|entry| Arc::as_ptr(&entry))
+            .collect();
+
+        let added: Vec<Arc<DesktopEntry>> = if self.only_unallocated {
+            matching
+                .into_iter()
+                .filter(|entry| taken.contains(&Arc::as_ptr(entry)))
+                .collect()
+        } else {
+            allocated.extend(matching.iter().map(Arc::as_ptr));
+            matching
+        };
+
+        self.insert(&added);
+        added
+    }
+
+    /// The second pass of [`distribute`], `entries` are the ones no category took. Returns the
+    /// entries this category took.
+    fn allocate_rest(&mut self, entries: &[Arc<DesktopEntry>]) -> Vec<Arc<DesktopEntry>> {
+        let matching: Vec<Arc<DesktopEntry>> = entries
+            .iter()
+            .filter(|entry| self.rule.matches(entry))
+            .cloned()
+            .collect();
+
+        let taken: HashSet<*const DesktopEntry> = self
+            .children
+            .iter_mut()
+            .flat_map(|child| child.allocate_rest(&matching))
+            .map(|entry| Arc::as_ptr(&entry))
+            .collect();
+
+        let added: Vec<Arc<DesktopEntry>> = if self.only_unallocated {
+            matching
+        } else {
+            matching
+                .into_iter()
+                .filter(|entry| taken.contains(&Arc::as_ptr(entry)))
+                .collect()
+        };
+
+        self.insert(&added);
+        added
+    }
+
+    /// Add entries without checking the rule, keeping them in the order of the layout.
+    fn insert(&mut self, entries: &[Arc<DesktopEntry>]) {
+        if entries.is_empty() {
+            return;
+        }
+
+        self.entries.extend(entries.iter().cloned());
+        let layout = &self.layout;
+
+        self.entries.sort_by_key(|e| {
+            (
+                layout
+                    .iter()
+                    .position(|id| *id == e.id)
+                    .unwrap_or(layout.len()),
+                e.name.to_lowercase(),
+            )
+        });
+    }
+
+    pub fn remove_entries(&mut self, entries: &[Arc<DesktopEntry>]) {
+        self.entries
+            .retain(|entry| !entries.iter().any(|removed| Arc::ptr_eq(entry, removed)));
+
+        for child in self.children.iter_mut() {
+            child.remove_entries(entries);
+        }
+    }
+
+    /// A copy without the sub-categories that have no entries, `None` if this one has none.
+    pub fn non_empty(&self) -> Option<Category> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        Some(Category {
+            children: self.children.iter().filter_map(|c| c.non_empty()).collect(),
+            ..self.clone()
+        })
+    }
+}
+
+/// Add entries to a tree of categories in the two passes of the menu specification. First every
+/// category takes the entries its rule matches, then those with `only_unallocated` get the
+/// matching entries no category anywhere in the tree took.
+pub fn distribute(categories: &mut [Category], entries: &[Arc<DesktopEntry>]) {
+    let entries: Vec<Arc<DesktopEntry>> = entries
+        .iter()
+        .filter(|entry| !entry.no_display)
+        .cloned()
+        .collect();
+    let mut allocated = HashSet::new();
+
+    for category in categories.iter_mut() {
+        category.allocate(&entries, &mut allocated);
+    }
+
+    let unallocated: Vec<Arc<DesktopEntry>> = entries
+        .into_iter()
+        .filter(|entry| !allocated.contains(&Arc::as_ptr(entry)))
+        .collect();
+
+    for category in categories.iter_mut() {
+        category.allocate_rest(&unallocated);
+    }
+}
+
+/// Split the name of a registered category into words, e.g. `Revision Control` for
+/// `RevisionControl` or `IRC Client` for `IRCClient`.
+pub fn display_name(category: &str) -> String {
+    let chars: Vec<char> = category.chars().collect();
+    let mut name = String::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && c.is_ascii_uppercase() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).map(|n| n.is_ascii_lowercase()) == Some(true);
+
+            if prev.is_ascii_lowercase() || (next_lower && prev.is_ascii_uppercase()) {
+                name.push(' ');
+            }
         }
+
+        name.push(*c);
     }
 
-    pub fn add_entries(&mut self, entries: &Vec<Arc<DesktopEntry>>) {
-        for entry in entries {
-            if entry
-                .categories
+    name
+}
+
+/// The main categories an additional category is used with, `None` if it isn't registered.
+pub fn related_categories(category: &str) -> Option<&'static [&'static str]> {
+    ADDITIONAL_CATEGORIES
+        .iter()
+        .find(|(additional, _)| *additional == category)
+        .map(|(_, related)| *related)
+}
+
+/// The main categories of an entry's `Categories`. Without any listed, each additional category
+/// implies the first main category it's used with. `Audio` & `Video` imply `AudioVideo`.
+pub fn main_categories(categories: &[String]) -> Vec<&'static str> {
+    let mut mains: Vec<&'static str> = MAIN_CATEGORIES
+        .iter()
+        .filter(|main| categories.iter().any(|c| c.eq_ignore_ascii_case(main)))
+        .copied()
+        .collect();
+
+    if mains.is_empty() {
+        for category in categories {
+            let related = ADDITIONAL_CATEGORIES
                 .iter()
-                .any(|c| c.to_lowercase() == self.iden.to_lowercase())
-            {
-                self.entries.push(entry.clone());
+                .find(|(additional, _)| additional.eq_ignore_ascii_case(category))
+                .and_then(|(_, related)| related.first());
+
+            if let Some(main) = related {
+                if !mains.contains(main) {
+                    mains.push(main);
+                }
             }
         }
+    }
+
+    if mains.iter().any(|m| *m == "Audio" || *m == "Video") && !mains.contains(&"AudioVideo") {
+        mains.push("AudioVideo");
+    }
+
+    mains
+}
+
+/// Main categories of the freedesktop menu specification.
+pub const MAIN_CATEGORIES: &[&str] = &[
+    "AudioVideo",
+    "Audio",
+    "Video",
+    "Development",
+    "Education",
+    "Game",
+    "Graphics",
+    "Network",
+    "Office",
+    "Science",
+    "Settings",
+    "System",
+    "Utility",
+];
+
+/// Additional categories of the freedesktop menu specification, with the main categories they
+/// are used with. The first one is where an entry listing no main category belongs. Toolkits,
+/// desktops & the like don't belong to any.
+pub const ADDITIONAL_CATEGORIES: &[(&str, &[&str])] = &[
+    ("Building", &["Development"]),
+    ("Debugger", &["Development"]),
+    ("IDE", &["Development"]),
+    ("GUIDesigner", &["Development"]),
+    ("Profiling", &["Development"]),
+    ("RevisionControl", &["Development"]),
+    ("Translation", &["Development"]),
+    ("Calendar", &["Office"]),
+    ("ContactManagement", &["Office"]),
+    ("Database", &["Office", "Development", "AudioVideo"]),
+    ("Dictionary", &["Office"]),
+    ("Chart", &["Office"]),
+    ("Email", &["Office", "Network"]),
+    ("Finance", &["Office"]),
+    ("FlowChart", &["Office"]),
+    ("PDA", &["Office"]),
+    ("ProjectManagement", &["Office", "Development"]),
+    ("Presentation", &["Office"]),
+    ("Spreadsheet", &["Office"]),
+    ("WordProcessor", &["Office"]),
+    ("2DGraphics", &["Graphics"]),
+    ("VectorGraphics", &["Graphics"]),
+    ("RasterGraphics", &["Graphics"]),
+    ("3DGraphics", &["Graphics"]),
+    ("Scanning", &["Graphics"]),
+    ("OCR", &["Graphics"]),
+    ("Photography", &["Graphics", "Office"]),
+    ("Publishing", &["Graphics", "Office"]),
+    ("Viewer", &["Graphics", "Office"]),
+    ("TextTools", &["Utility"]),
+    ("DesktopSettings", &["Settings"]),
+    ("HardwareSettings", &["Settings"]),
+    ("Printing", &["Settings"]),
+    ("PackageManager", &["Settings"]),
+    ("Dialup", &["Network"]),
+    ("InstantMessaging", &["Network"]),
+    ("Chat", &["Network"]),
+    ("IRCClient", &["Network"]),
+    ("Feed", &["Network"]),
+    ("FileTransfer", &["Network"]),
+    ("HamRadio", &["Network", "Audio"]),
+    ("News", &["Network"]),
+    ("P2P", &["Network"]),
+    ("RemoteAccess", &["Network"]),
+    ("Telephony", &["Network"]),
+    ("TelephonyTools", &["Utility"]),
+    ("VideoConference", &["Network"]),
+    ("WebBrowser", &["Network"]),
+    ("WebDevelopment", &["Network", "Development"]),
+    ("Midi", &["Audio"]),
+    ("Mixer", &["Audio"]),
+    ("Sequencer", &["Audio"]),
+    ("Tuner", &["Audio"]),
+    ("TV", &["Video"]),
+    ("AudioVideoEditing", &["Audio", "Video", "AudioVideo"]),
+    ("Player", &["Audio", "Video", "AudioVideo"]),
+    ("Recorder", &["Audio", "Video", "AudioVideo"]),
+    ("DiscBurning", &["AudioVideo"]),
+    ("ActionGame", &["Game"]),
+    ("AdventureGame", &["Game"]),
+    ("ArcadeGame", &["Game"]),
+    ("BoardGame", &["Game"]),
+    ("BlocksGame", &["Game"]),
+    ("CardGame", &["Game"]),
+    ("KidsGame", &["Game"]),
+    ("LogicGame", &["Game"]),
+    ("RolePlaying", &["Game"]),
+    ("Shooter", &["Game"]),
+    ("Simulation", &["Game"]),
+    ("SportsGame", &["Game"]),
+    ("StrategyGame", &["Game"]),
+    ("Art", &["Education", "Science"]),
+    ("Construction", &["Education", "Science"]),
+    ("Music", &["AudioVideo", "Education"]),
+    ("Languages", &["Education", "Science"]),
+    ("ArtificialIntelligence", &["Education", "Science"]),
+    ("Astronomy", &["Education", "Science"]),
+    ("Biology", &["Education", "Science"]),
+    ("Chemistry", &["Education", "Science"]),
+    ("ComputerScience", &["Education", "Science"]),
+    ("DataVisualization", &["Education", "Science"]),
+    ("Economy", &["Education", "Science"]),
+    ("Electricity", &["Education", "Science"]),
+    ("Geography", &["Education", "Science"]),
+    ("Geology", &["Education", "Science"]),
+    ("Geoscience", &["Education", "Science"]),
+    ("History", &["Education", "Science"]),
+    ("Humanities", &["Education", "Science"]),
+    ("ImageProcessing", &["Education", "Science"]),
+    ("Literature", &["Education", "Science"]),
+    ("Maps", &["Education", "Science", "Utility"]),
+    ("Math", &["Education", "Science"]),
+    ("NumericalAnalysis", &["Education", "Science"]),
+    ("MedicalSoftware", &["Education", "Science"]),
+    ("Physics", &["Education", "Science"]),
+    ("Robotics", &["Education", "Science"]),
+    ("Spirituality", &["Education", "Science", "Utility"]),
+    ("Sports", &["Education", "Science"]),
+    ("ParallelComputing", &["Education", "Science"]),
+    ("Amusement", &[]),
+    ("Archiving", &["Utility"]),
+    ("Compression", &["Utility"]),
+    ("Electronics", &[]),
+    ("Emulator", &["System", "Game"]),
+    ("Engineering", &[]),
+    ("FileTools", &["Utility", "System"]),
+    ("FileManager", &["System"]),
+    ("TerminalEmulator", &["System"]),
+    ("Filesystem", &["System"]),
+    ("Monitor", &["System", "Network"]),
+    ("Security", &["Settings", "System"]),
+    ("Accessibility", &["Settings", "Utility"]),
+    ("Calculator", &["Utility"]),
+    ("Clock", &["Utility"]),
+    ("TextEditor", &["Utility"]),
+    ("Documentation", &[]),
+    ("Adult", &[]),
+    ("Core", &[]),
+    ("KDE", &[]),
+    ("GNOME", &[]),
+    ("XFCE", &[]),
+    ("DDE", &[]),
+    ("GTK", &[]),
+    ("Qt", &[]),
+    ("Motif", &[]),
+    ("Java", &[]),
+    ("ConsoleOnly", &[]),
+];
+
+/// Reserved categories, only valid together with `OnlyShowIn`.
+pub const RESERVED_CATEGORIES: &[&str] = &["Screensaver", "TrayIcon", "Applet", "Shell"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desktop::test_entry;
+
+    fn list(value: &str) -> Vec<String> {
+        crate::keyfile::decode_list(value)
+    }
+
+    fn entry(id: &str, categories: &str) -> Arc<DesktopEntry> {
+        let keys = format!("Name={}\nExec={}\nCategories={}\n", id, id, categories);
+        Arc::new(test_entry(id, id, &keys))
+    }
+
+    fn ids(category: &Category) -> Vec<&str> {
+        category.entries.iter().map(|e| e.id.as_str()).collect()
+    }
+
+    #[test]
+    fn implied_main_categories() {
+        assert_eq!(main_categories(&list("Qt;KDE;")), Vec::<&str>::new());
+        assert_eq!(main_categories(&list("TextEditor;")), vec!["Utility"]);
+        assert_eq!(main_categories(&list("IDE;Qt;")), vec!["Development"]);
+        assert_eq!(main_categories(&list("Network;Email;")), vec!["Network"]);
+        assert_eq!(main_categories(&list("Email;")), vec!["Office"]);
+        assert_eq!(main_categories(&list("Midi;")), vec!["Audio", "AudioVideo"]);
+        assert_eq!(
+            main_categories(&list("Video;")),
+            vec!["Video", "AudioVideo"]
+        );
+        assert_eq!(main_categories(&list("game;x-custom;")), vec!["Game"]);
+    }
+
+    #[test]
+    fn registry() {
+        assert_eq!(
+            related_categories("Email"),
+            Some(&["Office", "Network"][..])
+        );
+        assert_eq!(related_categories("Qt"), Some(&[][..]));
+        assert_eq!(related_categories("Bogus"), None);
+
+        for (additional, related) in ADDITIONAL_CATEGORIES {
+            assert!(!MAIN_CATEGORIES.contains(additional));
+            assert!(related.iter().all(|main| MAIN_CATEGORIES.contains(main)));
+        }
+    }
+
+    #[test]
+    fn display_names() {
+        assert_eq!(display_name("RevisionControl"), "Revision Control");
+        assert_eq!(display_name("IRCClient"), "IRC Client");
+        assert_eq!(display_name("2DGraphics"), "2D Graphics");
+        assert_eq!(display_name("P2P"), "P2P");
+        assert_eq!(display_name("IDE"), "IDE");
+    }
+
+    #[test]
+    fn sub_categories_and_unallocated() {
+        let mut categories = vec![
+            Category::main_category("development", "Development", "applications-development"),
+            Category::main_category("audiovideo", "Multimedia", "applications-multimedia"),
+            Category::unallocated("other", "Other", "applications-other"),
+        ];
+
+        let entries = vec![
+            entry("ide.desktop", "Development;IDE;"),
+            entry("gdb.desktop", "Debugger;"),
+            entry("midi.desktop", "Midi;"),
+            entry("qt.desktop", "Qt;KDE;"),
+            entry("none.desktop", ""),
+        ];
+
+        distribute(&mut categories, &entries);
+
+        let development = &categories[0];
+        let child = |name: &str| {
+            development
+                .children
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+        };
+
+        assert_eq!(ids(development), vec!["gdb.desktop", "ide.desktop"]);
+        assert_eq!(ids(child("IDE")), vec!["ide.desktop"]);
+        assert_eq!(ids(child("Debugger")), vec!["gdb.desktop"]);
+        assert_eq!(child("IDE").iden, "development/ide");
+        assert_eq!(ids(&categories[1]), vec!["midi.desktop"]);
+        assert_eq!(ids(&categories[2]), vec!["none.desktop", "qt.desktop"]);
+
+        for category in categories.iter_mut() {
+            category.remove_entries(&entries[..1]);
+        }
+
+        let development = categories[0].non_empty().unwrap();
+        assert_eq!(ids(&development), vec!["gdb.desktop"]);
+        assert_eq!(
+            development
+                .children
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Debugger"]
+        );
+    }
+
+    #[test]
+    fn unallocated_across_the_tree() {
+        let mut tools = Category::new("tools", "Tools", "applications-utilities");
+        tools.rule = Rule::Category(String::from("Utility"));
+
+        let mut misc = Category::unallocated("misc/other", "Other", "applications-other");
+        misc.rule = Rule::Category(String::from("Utility"));
+
+        let mut debuggers = Category::new("misc/debuggers", "Debuggers", "applications-other");
+        debuggers.rule = Rule::Category(String::from("Debugger"));
+
+        let mut misc_parent = Category::unallocated("misc", "Misc", "applications-other");
+        misc_parent.children = vec![misc, debuggers];
+
+        let mut categories = vec![tools, misc_parent];
+        let entries = vec![
+            entry("calc.desktop", "Utility;"),
+            entry("gdb.desktop", "Debugger;"),
+            entry("note.desktop", "Office;"),
+        ];
+
+        distribute(&mut categories, &entries);
 
-        self.entries.sort_by_key(|e| e.name.to_lowercase());
+        // Nested menus with `only_unallocated` don't get entries another top-level menu took.
+        assert_eq!(ids(&categories[0]), vec!["calc.desktop"]);
+        assert_eq!(ids(&categories[1].children[0]), Vec::<&str>::new());
+        assert_eq!(ids(&categories[1].children[1]), vec!["gdb.desktop"]);
+        assert_eq!(ids(&categories[1]), vec!["gdb.desktop", "note.desktop"]);
     }
 }
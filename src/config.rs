@@ -0,0 +1,204 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::desktop::Origin;
+use crate::overrides::Edit;
+use crate::{appimage, desktop, keyfile};
+
+const USAGE: &str = "Usage: pyroxene [OPTIONS]
+       pyroxene [OPTIONS] --open <FILE>...
+       pyroxene validate [FILE...]
+       pyroxene default <MIME> [DESKTOP-ID]
+       pyroxene override <DESKTOP-ID> [--name <NAME>] [--categories <LIST>] [--hide | --show]
+       pyroxene autostart [--dry-run]
+
+Commands:
+    validate                Check desktop files, or every discovered one, for errors
+    default                 Print the default application for a MIME type, or set it in
+                            $XDG_CONFIG_HOME/mimeapps.list
+    override                Rename, recategorize or hide an entry with a copy of its desktop
+                            file in $XDG_DATA_HOME/applications
+    autostart               Launch the entries of the XDG autostart directories, --dry-run only
+                            lists them
+
+Options:
+    --show-missing          Show entries whose TryExec binary is missing greyed out
+    --search-no-display     Let search find entries with NoDisplay=true
+    --desktop <NAMES>       Colon-separated desktop names used for OnlyShowIn/NotShowIn,
+                            defaults to $XDG_CURRENT_DESKTOP
+    --open <FILE>...        Choose an application to open the files with
+    --prefer <SOURCE>       Hide entries also installed from SOURCE: native, flatpak, snap or
+                            appimage
+    --no-cache              Parse every desktop file instead of using the cache in
+                            $XDG_CACHE_HOME/pyroxene, and don't update it
+    --appimage-dir <DIR>    Look for AppImages in DIR instead of ~/Applications, can be repeated
+    -h, --help              Print this message";
+
+#[derive(Clone, Debug, Default)]
+pub enum Command {
+    #[default]
+    Menu,
+    Validate(Vec<PathBuf>),
+    Open(Vec<PathBuf>),
+    Default(String, Option<String>),
+    Override(String, Vec<Edit>),
+    /// Launch the autostart entries, or only list them if `true`.
+    Autostart(bool),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub command: Command,
+    /// Show entries whose `TryExec` binary is missing greyed out instead of dropping them.
+    pub show_missing: bool,
+    /// Let search find entries with `NoDisplay=true`. They are never shown in the categories.
+    pub search_no_display: bool,
+    /// Desktop names used for `OnlyShowIn` & `NotShowIn`.
+    pub desktops: Vec<String>,
+    /// Hide entries whose application is also installed from this origin.
+    pub prefer: Option<Origin>,
+    /// Don't read or write the cache of parsed desktop files.
+    pub no_cache: bool,
+    /// Directories searched for AppImages.
+    pub appimage_dirs: Vec<PathBuf>,
+    /// Directories of desktop files besides the application directories, the `<AppDir>`s of the
+    /// menu file.
+    pub app_dirs: Vec<PathBuf>,
+}
+
+impl Config {
+    pub fn from_args() -> Result<Self, String> {
+        let mut config = Config {
+            desktops: desktop::current_desktops(),
+            appimage_dirs: appimage::default_dirs(),
+            ..Config::default()
+        };
+
+        let mut args = env::args().skip(1).peekable();
+        let mut appimage_dirs = Vec::new();
+
+        match args.peek().map(|a| a.as_str()) {
+            Some("validate") => {
+                args.next();
+                config.command = Command::Validate(Vec::new());
+            },
+            Some("default") => {
+                args.next();
+                let mime = args
+                    .next()
+                    .filter(|a| !a.starts_with('-'))
+                    .ok_or_else(|| format!("Missing MIME type for default\n\n{}", USAGE))?;
+                let id = match args.peek() {
+                    Some(id) if !id.starts_with('-') => args.next(),
+                    _ => None,
+                };
+                config.command = Command::Default(mime, id);
+            },
+            Some("override") => {
+                args.next();
+                let id = args
+                    .next()
+                    .filter(|a| !a.starts_with('-'))
+                    .ok_or_else(|| format!("Missing desktop-file ID for override\n\n{}", USAGE))?;
+                config.command = Command::Override(id, Vec::new());
+            },
+            Some("autostart") => {
+                args.next();
+                config.command = Command::Autostart(false);
+            },
+            _ => (),
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--show-missing" => config.show_missing = true,
+                "--search-no-display" => config.search_no_display = true,
+                "--no-cache" => config.no_cache = true,
+                "--desktop" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {}\n\n{}", arg, USAGE))?;
+                    config.desktops = desktop::parse_desktops(&value);
+                },
+                "--prefer" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {}\n\n{}", arg, USAGE))?;
+                    config.prefer = Some(
+                        Origin::parse(&value)
+                            .ok_or_else(|| format!("Unknown source: {}\n\n{}", value, USAGE))?,
+                    );
+                },
+                "--appimage-dir" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("Missing value for {}\n\n{}", arg, USAGE))?;
+                    appimage_dirs.push(shellexpand::tilde(&value).into_owned().into());
+                },
+                "--open" => {
+                    match config.command {
+                        Command::Menu => config.command = Command::Open(Vec::new()),
+                        _ => return Err(format!("Unknown argument: {}\n\n{}", arg, USAGE)),
+                    }
+                },
+                "--dry-run" => {
+                    match &mut config.command {
+                        Command::Autostart(dry_run) => *dry_run = true,
+                        _ => return Err(format!("Unknown argument: {}\n\n{}", arg, USAGE)),
+                    }
+                },
+                "--name" | "--categories" | "--hide" | "--show" => {
+                    let edits = match &mut config.command {
+                        Command::Override(_, edits) => edits,
+                        _ => return Err(format!("Unknown argument: {}\n\n{}", arg, USAGE)),
+                    };
+
+                    let edit = match arg.as_str() {
+                        "--hide" => Edit::NoDisplay(true),
+                        "--show" => Edit::NoDisplay(false),
+                        _ => {
+                            let value = args
+                                .next()
+                                .ok_or_else(|| format!("Missing value for {}\n\n{}", arg, USAGE))?;
+
+                            if arg == "--name" {
+                                Edit::Name(value)
+                            } else {
+                                Edit::Categories(keyfile::decode_list(&value))
+                            }
+                        },
+                    };
+
+                    edits.push(edit);
+                },
+                "-h" | "--help" => return Err(USAGE.to_owned()),
+                _ => {
+                    match &mut config.command {
+                        Command::Validate(files) | Command::Open(files)
+                            if !arg.starts_with('-') =>
+                        {
+                            files.push(arg.into())
+                        },
+                        _ => return Err(format!("Unknown argument: {}\n\n{}", arg, USAGE)),
+                    }
+                },
+            }
+        }
+
+        if !appimage_dirs.is_empty() {
+            config.appimage_dirs = appimage_dirs;
+        }
+
+        match &config.command {
+            Command::Open(files) if files.is_empty() => {
+                return Err(format!("Missing value for --open\n\n{}", USAGE));
+            },
+            Command::Override(_, edits) if edits.is_empty() => {
+                return Err(format!("Nothing to override\n\n{}", USAGE));
+            },
+            _ => (),
+        }
+
+        Ok(config)
+    }
+}
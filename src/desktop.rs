@@ -1,133 +1,570 @@
-use std::fs::File;
-use std::io;
-use std::io::Read;
-use std::path::Path;
+use std::error::Error;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+use std::{env, fmt, io};
+
+use crate::appstream::{Catalog, Component};
+use crate::exec;
+use crate::keyfile::{Group, KeyFile, KeyFileErr};
+use crate::locale::Locale;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DesktopEntryKind {
+    Application,
+    Link,
+    /// Names a menu category, see [`crate::directory`].
+    Directory,
+}
+
+impl DesktopEntryKind {
+    /// The kind of a `Type` value, `Application` if it is missing. `None` if it isn't supported.
+    pub fn parse(value: Option<&str>) -> Option<Self> {
+        match value {
+            None | Some("Application") => Some(DesktopEntryKind::Application),
+            Some("Link") => Some(DesktopEntryKind::Link),
+            Some("Directory") => Some(DesktopEntryKind::Directory),
+            Some(_) => None,
+        }
+    }
+}
+
+/// How the application of an entry was installed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Origin {
+    Native,
+    Flatpak,
+    Snap,
+    AppImage,
+}
+
+impl Origin {
+    /// Recognize Flatpak & Snap exports by their `X-Flatpak` & `X-SnapInstanceName` keys, or by
+    /// the directory they were exported to. Desktop files extracted from AppImages are marked with
+    /// `X-AppImage-Path`.
+    pub fn detect(group: &Group, path: &Path) -> Self {
+        if group.entry("X-Flatpak").is_some()
+            || path.to_string_lossy().contains("/flatpak/exports/")
+        {
+            Origin::Flatpak
+        } else if group.entry("X-SnapInstanceName").is_some()
+            || path.starts_with("/var/lib/snapd/desktop")
+        {
+            Origin::Snap
+        } else if group.entry("X-AppImage-Path").is_some() {
+            Origin::AppImage
+        } else {
+            Origin::Native
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "native" => Some(Origin::Native),
+            "flatpak" => Some(Origin::Flatpak),
+            "snap" => Some(Origin::Snap),
+            "appimage" => Some(Origin::AppImage),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Origin::Native => "native",
+            Origin::Flatpak => "flatpak",
+            Origin::Snap => "snap",
+            Origin::AppImage => "appimage",
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct DesktopEntry {
+    pub kind: DesktopEntryKind,
+    pub id: String,
+    pub source: PathBuf,
+    /// When `source` was modified as it was parsed, see [`mtime`].
+    pub modified: u64,
+    pub origin: Origin,
     pub name: String,
+    pub untranslated_name: String,
     pub icon: Option<String>,
     pub generic_name: Option<String>,
     pub comment: Option<String>,
-    pub exec: String,
+    pub exec: Option<String>,
+    pub url: Option<String>,
     pub path: Option<String>,
     pub terminal: bool,
     pub categories: Vec<String>,
     pub keywords: Vec<String>,
+    pub mime_types: Vec<String>,
+    pub actions: Vec<DesktopAction>,
+    pub try_exec: Option<String>,
+    pub try_exec_failed: bool,
+    pub no_display: bool,
+    pub only_show_in: Vec<String>,
+    pub not_show_in: Vec<String>,
+    pub startup_wm_class: Option<String>,
+    /// The Flatpak app ID from `X-Flatpak`.
+    pub flatpak_id: Option<String>,
+    /// The parsed file, including everything the fields above don't model. Entries taken from the
+    /// cache read it from `source` when it's first needed, see [`DesktopEntry::document`].
+    pub document: OnceLock<KeyFile>,
+    /// AppStream metadata, see [`DesktopEntry::component`].
+    pub appstream: Catalog,
+}
+
+#[derive(Clone, Debug)]
+pub struct DesktopAction {
+    pub id: String,
+    pub name: String,
+    pub untranslated_name: String,
+    pub icon: Option<String>,
+    pub exec: String,
 }
 
 #[derive(Debug)]
 pub enum DesktopEntryErr {
-    InvalidGroupHeader,
-    NotApplication,
+    Parse(KeyFileErr),
+    MissingDesktopEntryGroup,
+    UnsupportedType(String),
     Hidden,
     OnlyShowIn,
     NotShowIn,
     MissingName,
     MissingExec,
+    MissingUrl,
+    TryExec(Box<DesktopEntry>),
     IO(io::Error),
 }
 
+impl From<KeyFileErr> for DesktopEntryErr {
+    fn from(e: KeyFileErr) -> Self {
+        match e {
+            KeyFileErr::IO(e) => DesktopEntryErr::IO(e),
+            e => DesktopEntryErr::Parse(e),
+        }
+    }
+}
+
+impl fmt::Display for DesktopEntryErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DesktopEntryErr::Parse(e) => write!(f, "{}", e),
+            DesktopEntryErr::MissingDesktopEntryGroup => {
+                write!(f, "missing [Desktop Entry] group")
+            },
+            DesktopEntryErr::UnsupportedType(ty) => write!(f, "unsupported Type: {}", ty),
+            DesktopEntryErr::Hidden => write!(f, "entry is hidden"),
+            DesktopEntryErr::OnlyShowIn => write!(f, "not in OnlyShowIn for the current desktop"),
+            DesktopEntryErr::NotShowIn => write!(f, "in NotShowIn for the current desktop"),
+            DesktopEntryErr::MissingName => write!(f, "missing required key Name"),
+            DesktopEntryErr::MissingExec => write!(f, "missing required key Exec"),
+            DesktopEntryErr::MissingUrl => write!(f, "missing required key URL"),
+            DesktopEntryErr::TryExec(entry) => {
+                write!(
+                    f,
+                    "TryExec binary not found: {}",
+                    entry.try_exec.as_deref().unwrap_or("")
+                )
+            },
+            DesktopEntryErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for DesktopEntryErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DesktopEntryErr::Parse(e) => Some(e),
+            DesktopEntryErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl DesktopEntry {
+    /// Parse a desktop file using its file name as the desktop-file ID and `$XDG_CURRENT_DESKTOP`
+    /// for `OnlyShowIn` & `NotShowIn`.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, DesktopEntryErr> {
-        let mut handle = File::open(path).map_err(|e| DesktopEntryErr::IO(e))?;
-        let mut buffer = String::new();
-        handle
-            .read_to_string(&mut buffer)
-            .map_err(|e| DesktopEntryErr::IO(e))?;
-
-        let mut name: Option<String> = None; // R
-        let mut icon: Option<String> = None;
-        let mut generic_name: Option<String> = None;
-        let mut comment: Option<String> = None;
-        let mut exec: Option<String> = None; // R
-        let mut path: Option<String> = None;
-        let mut terminal: Option<bool> = None;
-        let mut categories: Vec<String> = Vec::new();
-        let mut keywords: Vec<String> = Vec::new();
-        let mut current_group: Option<String> = None;
-
-        for line in buffer.lines() {
-            if line.starts_with("[") {
-                if line.len() < 3 {
-                    return Err(DesktopEntryErr::InvalidGroupHeader);
-                }
-
-                current_group = Some(line[1..(line.len() - 1)].to_owned());
-                continue;
-            }
+        let id = path
+            .as_ref()
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::with_id(id, path, &current_desktops())
+    }
 
-            if current_group.is_some()
-                && current_group.as_ref().unwrap().as_str() == "Desktop Entry"
-            {
-                if line.starts_with("Type=") {
-                    if !line.ends_with("Application") {
-                        return Err(DesktopEntryErr::NotApplication);
-                    }
-                } else if line.starts_with("Name=") {
-                    name = Some(line[5..].to_owned());
-                } else if line.starts_with("Icon=") {
-                    icon = Some(line[5..].to_owned());
-                } else if line.starts_with("GenericName=") {
-                    generic_name = Some(line[12..].to_owned());
-                } else if line.starts_with("Comment=") {
-                    comment = Some(line[8..].to_owned());
-                } else if line.starts_with("Exec=") {
-                    exec = Some(line[5..].to_owned());
-                } else if line.starts_with("Path=") {
-                    path = Some(line[5..].to_owned());
-                } else if line.starts_with("Terminal") {
-                    if line.ends_with("true") {
-                        terminal = Some(true);
-                    } else {
-                        terminal = Some(false);
-                    }
-                } else if line.starts_with("Categories=") {
-                    for category in line[11..].split(";") {
-                        if !category.is_empty() {
-                            categories.push(category.to_owned());
-                        }
-                    }
-                } else if line.starts_with("Keywords=") {
-                    for keyword in line[9..].split(";") {
-                        if !keyword.is_empty() {
-                            keywords.push(keyword.to_owned());
-                        }
-                    }
-                } else if line.starts_with("Hidden=") {
-                    if line.ends_with("true") {
-                        return Err(DesktopEntryErr::Hidden);
-                    }
-                } else if line.starts_with("OnlyShowIn=") {
-                    if !line[11..]
-                        .split(";")
-                        .map(|v| v.to_owned())
-                        .any(|i| i == "Sway")
-                    {
-                        return Err(DesktopEntryErr::OnlyShowIn);
-                    }
-                } else if line.starts_with("NotShowIn=") {
-                    if line[10..]
-                        .split(";")
-                        .map(|v| v.to_owned())
-                        .any(|i| i == "Sway")
-                    {
-                        return Err(DesktopEntryErr::NotShowIn);
-                    }
-                }
-            }
+    pub fn with_id<I: Into<String>, P: AsRef<Path>>(
+        id: I,
+        path: P,
+        desktops: &[String],
+    ) -> Result<Self, DesktopEntryErr> {
+        let source = path.as_ref().to_path_buf();
+        let key_file = KeyFile::open(path)?;
+        Self::from_key_file(id, source, key_file, desktops)
+    }
+
+    /// Build an entry from an already parsed desktop file. `source` is where it came from.
+    pub fn from_key_file<I: Into<String>>(
+        id: I,
+        source: PathBuf,
+        key_file: KeyFile,
+        desktops: &[String],
+    ) -> Result<Self, DesktopEntryErr> {
+        Self::parse(id, source, key_file)?.check(desktops)
+    }
+
+    /// Like [`DesktopEntry::from_key_file`] without the checks that depend on the environment,
+    /// see [`DesktopEntry::check`].
+    pub fn parse<I: Into<String>>(
+        id: I,
+        source: PathBuf,
+        key_file: KeyFile,
+    ) -> Result<Self, DesktopEntryErr> {
+        let group = key_file
+            .group("Desktop Entry")
+            .ok_or(DesktopEntryErr::MissingDesktopEntryGroup)?;
+        let locale = Locale::from_env();
+
+        let kind = DesktopEntryKind::parse(group.raw("Type")).ok_or_else(|| {
+            DesktopEntryErr::UnsupportedType(group.raw("Type").unwrap_or_default().to_owned())
+        })?;
+
+        if group.boolean("Hidden")? == Some(true) {
+            return Err(DesktopEntryErr::Hidden);
         }
 
+        let name = group.localized_string("Name", locale.as_ref());
+        let untranslated_name = name.default.clone().ok_or(DesktopEntryErr::MissingName)?;
+        let exec = group.string("Exec");
+        let url = group.string("URL");
+
+        match kind {
+            DesktopEntryKind::Application if exec.is_none() => {
+                return Err(DesktopEntryErr::MissingExec)
+            },
+            DesktopEntryKind::Link if url.is_none() => return Err(DesktopEntryErr::MissingUrl),
+            _ => (),
+        }
+
+        let actions = group
+            .list("Actions")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|id| {
+                let action_group = key_file.group(&format!("Desktop Action {}", id))?;
+                let name = action_group.localized_string("Name", locale.as_ref());
+                let untranslated_name = name.default.clone()?;
+
+                Some(DesktopAction {
+                    name: name
+                        .into_best()
+                        .unwrap_or_else(|| untranslated_name.clone()),
+                    untranslated_name,
+                    icon: action_group
+                        .localized_string("Icon", locale.as_ref())
+                        .into_best(),
+                    exec: action_group.string("Exec")?,
+                    id,
+                })
+            })
+            .collect();
+
         Ok(DesktopEntry {
-            name: name.ok_or(DesktopEntryErr::MissingName)?,
-            icon,
-            generic_name,
-            comment,
-            exec: exec.ok_or(DesktopEntryErr::MissingExec)?,
-            path,
-            terminal: terminal.unwrap_or(false),
-            categories,
-            keywords,
+            kind,
+            id: id.into(),
+            origin: Origin::detect(group, &source),
+            modified: mtime(&source),
+            source,
+            name: name
+                .into_best()
+                .unwrap_or_else(|| untranslated_name.clone()),
+            untranslated_name,
+            icon: group.localized_string("Icon", locale.as_ref()).into_best(),
+            generic_name: group
+                .localized_string("GenericName", locale.as_ref())
+                .into_best(),
+            comment: group
+                .localized_string("Comment", locale.as_ref())
+                .into_best(),
+            exec,
+            url,
+            path: group.string("Path"),
+            terminal: group.boolean("Terminal")?.unwrap_or(false),
+            categories: group.list("Categories").unwrap_or_default(),
+            keywords: group
+                .localized_list("Keywords", locale.as_ref())
+                .into_best()
+                .unwrap_or_default(),
+            mime_types: group.list("MimeType").unwrap_or_default(),
+            actions,
+            try_exec: group.string("TryExec"),
+            try_exec_failed: false,
+            no_display: group.boolean("NoDisplay")?.unwrap_or(false),
+            only_show_in: group.list("OnlyShowIn").unwrap_or_default(),
+            not_show_in: group.list("NotShowIn").unwrap_or_default(),
+            startup_wm_class: group.string("StartupWMClass"),
+            flatpak_id: group.string("X-Flatpak"),
+            // Moved in last, the fields above borrow from it.
+            document: OnceLock::from(key_file),
+            appstream: Catalog::default(),
         })
     }
+
+    /// Check `OnlyShowIn` & `NotShowIn` against `desktops` and look for the `TryExec` binary.
+    pub fn check(mut self, desktops: &[String]) -> Result<Self, DesktopEntryErr> {
+        self.check_show_in(desktops)?;
+        self.try_exec_failed = false;
+
+        if let Some(try_exec) = self.try_exec.as_ref() {
+            if find_executable(try_exec).is_none() {
+                self.try_exec_failed = true;
+                return Err(DesktopEntryErr::TryExec(Box::new(self)));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// The parsed file, read from `source` if the entry came from the cache. Empty if that fails.
+    pub fn document(&self) -> &KeyFile {
+        self.document.get_or_init(|| {
+            KeyFile::open(&self.source).unwrap_or_else(|e| {
+                println!(
+                    "Warning: Failed to read desktop file: {:?} ({})",
+                    self.source, e
+                );
+                KeyFile::default()
+            })
+        })
+    }
+
+    /// A group of the desktop file, e.g. `Desktop Action new-window` or a vendor `X-` group.
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.document().group(name)
+    }
+
+    fn main_group(&self) -> Option<&Group> {
+        self.group("Desktop Entry")
+    }
+
+    /// The raw value of any key of the `[Desktop Entry]` group, including `X-` keys.
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.main_group()?.raw(key)
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.main_group()?.string(key)
+    }
+
+    /// `None` if the key is missing or isn't a boolean.
+    pub fn boolean(&self, key: &str) -> Option<bool> {
+        self.main_group()?.boolean(key).ok().flatten()
+    }
+
+    pub fn list(&self, key: &str) -> Option<Vec<String>> {
+        self.main_group()?.list(key)
+    }
+
+    /// The best variant of `key` for `locale`.
+    pub fn localized_string(&self, key: &str, locale: Option<&Locale>) -> Option<String> {
+        self.main_group()?.localized_string(key, locale).into_best()
+    }
+
+    /// The best variant of `key` for `locale`.
+    pub fn localized_list(&self, key: &str, locale: Option<&Locale>) -> Option<Vec<String>> {
+        self.main_group()?.localized_list(key, locale).into_best()
+    }
+
+    pub fn prefers_non_default_gpu(&self) -> bool {
+        self.boolean("PrefersNonDefaultGPU").unwrap_or(false)
+    }
+
+    /// Entries of the same application installed from different origins share this key: the last
+    /// component of the Flatpak app ID or of a reverse-DNS desktop-file ID, e.g. `firefox` for
+    /// `org.mozilla.firefox`, `StartupWMClass` or the name of the program `Exec` runs. The
+    /// untranslated name is only the last resort.
+    pub fn duplicate_key(&self) -> String {
+        let id = self.id.strip_suffix(".desktop").unwrap_or(&self.id);
+        let app_id = self
+            .flatpak_id
+            .as_deref()
+            .or_else(|| Some(id).filter(|id| id.matches('.').count() >= 2));
+
+        app_id
+            .and_then(|app_id| app_id.rsplit('.').next())
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_owned())
+            .or_else(|| self.startup_wm_class.clone())
+            .or_else(|| self.program())
+            .unwrap_or_else(|| self.untranslated_name.clone())
+            .to_lowercase()
+    }
+
+    /// The file name of the program `Exec` runs, skipping an `env` wrapper & its variables.
+    fn program(&self) -> Option<String> {
+        let args = exec::split_exec(self.exec.as_ref()?).ok()?;
+        let mut args = args.iter();
+        let mut program = args.next()?;
+
+        if program == "env" || program.ends_with("/env") {
+            program = args.find(|arg| !arg.starts_with('-') && !arg.contains('='))?;
+        }
+
+        program
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_owned())
+    }
+
+    /// The AppStream metadata of the application, if it ships any & it is loaded.
+    pub fn component(&self) -> Option<&Component> {
+        self.appstream.get(&self.id)
+    }
+
+    /// Words search matches on besides the name: `Keywords`, the AppStream keywords & summary.
+    pub fn search_terms(&self) -> Vec<&str> {
+        let mut terms: Vec<&str> = self.keywords.iter().map(|k| k.as_str()).collect();
+
+        if let Some(component) = self.component() {
+            terms.extend(component.keywords.iter().map(|k| k.as_str()));
+            terms.extend(component.summary.as_deref());
+        }
+
+        terms
+    }
+
+    /// Check `OnlyShowIn` & `NotShowIn` against the current desktops. The first desktop listed
+    /// in either key decides, otherwise the entry is shown unless it has `OnlyShowIn`.
+    pub fn check_show_in(&self, desktops: &[String]) -> Result<(), DesktopEntryErr> {
+        for desktop in desktops {
+            if self.only_show_in.contains(desktop) {
+                return Ok(());
+            }
+
+            if self.not_show_in.contains(desktop) {
+                return Err(DesktopEntryErr::NotShowIn);
+            }
+        }
+
+        if self.only_show_in.is_empty() {
+            Ok(())
+        } else {
+            Err(DesktopEntryErr::OnlyShowIn)
+        }
+    }
+}
+
+/// Modification time in nanoseconds, 0 if the file doesn't exist.
+pub fn mtime(path: &Path) -> u64 {
+    path.metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// The colon-separated desktop names in `$XDG_CURRENT_DESKTOP`.
+pub fn current_desktops() -> Vec<String> {
+    env::var("XDG_CURRENT_DESKTOP")
+        .map(|v| parse_desktops(&v))
+        .unwrap_or_default()
+}
+
+pub fn parse_desktops(value: &str) -> Vec<String> {
+    value
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(|d| d.to_owned())
+        .collect()
+}
+
+/// Resolve an absolute path or a name in `$PATH` to an executable file.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if name.contains('/') {
+        let path = PathBuf::from(name);
+        return if path.is_absolute() && is_executable(&path) {
+            Some(path)
+        } else {
+            None
+        };
+    }
+
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| is_executable(path))
+}
+
+/// An entry parsed from the `[Desktop Entry]` group with `keys`, for the tests of every module.
+#[cfg(test)]
+pub fn test_entry(id: &str, path: &str, keys: &str) -> DesktopEntry {
+    let text = format!("[Desktop Entry]\n{}", keys);
+    DesktopEntry::parse(id, PathBuf::from(path), KeyFile::parse(&text).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_keys() {
+        let native = test_entry(
+            "firefox.desktop",
+            "/usr/share/applications/firefox.desktop",
+            "Name=Firefox\nExec=env MOZ_ENABLE_WAYLAND=1 /usr/lib/firefox/firefox %u\n",
+        );
+        let snap = test_entry(
+            "firefox_firefox.desktop",
+            "/var/lib/snapd/desktop/applications/firefox_firefox.desktop",
+            "Name=Firefox\nExec=/snap/bin/firefox %u\nX-SnapInstanceName=firefox\n",
+        );
+        let flatpak = test_entry(
+            "org.mozilla.firefox.desktop",
+            "/var/lib/flatpak/exports/share/applications/org.mozilla.firefox.desktop",
+            "Name=Firefox\nExec=/usr/bin/flatpak run org.mozilla.firefox\n\
+             X-Flatpak=org.mozilla.firefox\n",
+        );
+        let named = test_entry(
+            "browser.desktop",
+            "/opt/browser.desktop",
+            "Name=Firefox\nType=Link\nURL=x\n",
+        );
+
+        assert_eq!(native.duplicate_key(), "firefox");
+        assert_eq!(snap.origin, Origin::Snap);
+        assert_eq!(snap.duplicate_key(), native.duplicate_key());
+        assert_eq!(native.duplicate_key(), flatpak.duplicate_key());
+        assert_eq!(named.duplicate_key(), "firefox");
+    }
+
+    #[test]
+    fn kinds() {
+        let link = test_entry(
+            "intranet.desktop",
+            "/opt/intranet.desktop",
+            "Name=Intranet\nType=Link\nURL=x\n",
+        );
+        let directory = test_entry(
+            "web.directory",
+            "/opt/web.directory",
+            "Name=Web\nType=Directory\n",
+        );
+        let text = "[Desktop Entry]\nType=Service\nName=Daemon\n";
+        let service = DesktopEntry::parse("d", PathBuf::from("d"), KeyFile::parse(text).unwrap());
+
+        assert_eq!(link.kind, DesktopEntryKind::Link);
+        assert_eq!(link.url.as_deref(), Some("x"));
+        assert_eq!(directory.kind, DesktopEntryKind::Directory);
+        assert!(matches!(service, Err(DesktopEntryErr::UnsupportedType(t)) if t == "Service"));
+    }
 }
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::category::Category;
+use crate::desktop::DesktopEntryKind;
+use crate::keyfile::{KeyFile, KeyFileErr};
+use crate::locale::Locale;
+use crate::xdg;
+
+/// The localized name, comment & icon of a `.directory` file, a `Type=Directory` entry, each only
+/// if it is set.
+#[derive(Clone, Debug, Default)]
+pub struct Directory {
+    pub name: Option<String>,
+    pub comment: Option<String>,
+    pub icon: Option<String>,
+}
+
+impl Directory {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, KeyFileErr> {
+        let key_file = KeyFile::open(path)?;
+        let locale = Locale::from_env();
+
+        let group = match key_file.group("Desktop Entry") {
+            Some(some) => some,
+            None => return Ok(Directory::default()),
+        };
+
+        // Files overriding only some keys may leave out the `Type`, other entries name nothing.
+        if group.raw("Type").is_some()
+            && DesktopEntryKind::parse(group.raw("Type")) != Some(DesktopEntryKind::Directory)
+        {
+            return Ok(Directory::default());
+        }
+
+        Ok(Directory {
+            name: group.localized_string("Name", locale.as_ref()).into_best(),
+            comment: group
+                .localized_string("Comment", locale.as_ref())
+                .into_best(),
+            icon: group.localized_string("Icon", locale.as_ref()).into_best(),
+        })
+    }
+}
+
+/// `desktop-directories` in the data dirs, least important first.
+pub fn default_dirs() -> Vec<PathBuf> {
+    xdg::data_dirs()
+        .into_iter()
+        .rev()
+        .map(|dir| dir.join("desktop-directories"))
+        .collect()
+}
+
+/// The files of the directories `.directory` files are looked up in. Each directory is only read
+/// once, however many categories look in it.
+#[derive(Debug, Default)]
+pub struct DirectoryFiles(HashMap<PathBuf, HashMap<String, PathBuf>>);
+
+impl DirectoryFiles {
+    /// The files in `dir` keyed by their lowercase name, none if it can't be read.
+    fn files(&mut self, dir: &Path) -> &HashMap<String, PathBuf> {
+        self.0.entry(dir.to_path_buf()).or_insert_with(|| {
+            dir.read_dir()
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .filter_map(|e| Some((e.file_name().to_str()?.to_lowercase(), e.path())))
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+    }
+}
+
+/// Apply the `.directory` files called `names` to a category, matching the file names
+/// case-insensitively. Every file found is applied in the order of `dirs`, so a file in a later
+/// directory only needs the keys it overrides, e.g. just the `Icon`.
+pub fn apply(
+    category: &mut Category,
+    names: &[String],
+    dirs: &[PathBuf],
+    directory_files: &mut DirectoryFiles,
+) {
+    for dir in dirs {
+        let files = directory_files.files(dir);
+        // The last name takes precedence.
+        let paths: Vec<PathBuf> = names
+            .iter()
+            .filter_map(|name| files.get(&name.to_lowercase()).cloned())
+            .collect();
+
+        for path in paths {
+            match Directory::open(&path) {
+                Ok(directory) => category.apply_directory(&directory),
+                Err(e) => {
+                    println!(
+                        "Warning: Failed to parse directory file: {:?} ({})",
+                        path, e
+                    )
+                },
+            }
+        }
+    }
+}
+
+/// Apply `<iden>.directory` from the data dirs to every category, e.g. `Utility.directory` for
+/// the `utility` category. One in `$XDG_DATA_HOME/desktop-directories` can override the name or
+/// icon of any category. Sub-categories use the last part of their iden, e.g. `IDE.directory` for
+/// `development/ide`.
+pub fn apply_all(categories: &mut [Category]) {
+    apply_tree(categories, &default_dirs(), &mut DirectoryFiles::default());
+}
+
+fn apply_tree(categories: &mut [Category], dirs: &[PathBuf], directory_files: &mut DirectoryFiles) {
+    for category in categories.iter_mut() {
+        let name = category.iden.rsplit('/').next().unwrap_or_default();
+        let names = vec![format!("{}.directory", name)];
+        apply(category, &names, dirs, directory_files);
+        apply_tree(&mut category.children, dirs, directory_files);
+    }
+}
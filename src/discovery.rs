@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::xdg;
+
+#[derive(Clone, Debug)]
+pub struct DesktopFile {
+    pub id: String,
+    pub path: PathBuf,
+}
+
+/// Where Flatpak & Snap export their data. These are only part of `$XDG_DATA_DIRS` if the
+/// environment setup of Flatpak or Snap was sourced.
+pub fn export_dirs() -> Vec<PathBuf> {
+    vec![
+        xdg::data_home()
+            .join("flatpak")
+            .join("exports")
+            .join("share"),
+        PathBuf::from("/var/lib/flatpak/exports/share"),
+        PathBuf::from("/var/lib/snapd/desktop"),
+    ]
+}
+
+/// The `applications` directories of the XDG data dirs, most important first, followed by those
+/// of the export dirs that aren't data dirs.
+pub fn application_dirs() -> Vec<PathBuf> {
+    let mut dirs = xdg::data_dirs();
+
+    for dir in export_dirs() {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    dirs.into_iter()
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// All `.desktop` files in the application directories. When multiple files share a desktop-file
+/// ID only the one from the most important directory is returned. Each directory is scanned on a
+/// thread of its own.
+pub fn desktop_files() -> Vec<DesktopFile> {
+    let scans: Vec<_> = application_dirs()
+        .into_iter()
+        .filter(|dir| dir.exists())
+        .map(|dir| thread::spawn(move || scan_dir(&dir, "desktop")))
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+
+    for scan in scans {
+        for file in scan.join().unwrap_or_default() {
+            if seen.insert(file.id.clone()) {
+                files.push(file);
+            }
+        }
+    }
+
+    files
+}
+
+/// Recursively find the files with `extension` in `root`, keyed by their desktop-file ID.
+pub fn scan_dir(root: &Path, extension: &str) -> Vec<DesktopFile> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        match dir.read_dir() {
+            Err(e) => println!("Warning: Failed to read directory: {:?} ({})", dir, e),
+            Ok(entries) => {
+                for entry_result in entries {
+                    match entry_result {
+                        Err(e) => println!("Warning: Failed to read directory entry: {}", e),
+                        Ok(entry) => {
+                            let entry_path = entry.path();
+
+                            // Symlinked directories aren't followed, they may point back up.
+                            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                                dirs.push(entry_path);
+                            } else if entry_path.is_file()
+                                && entry_path.extension().map(|e| e == extension) == Some(true)
+                            {
+                                if let Some(id) = file_id(root, &entry_path) {
+                                    files.push(DesktopFile {
+                                        id,
+                                        path: entry_path,
+                                    });
+                                }
+                            }
+                        },
+                    }
+                }
+            },
+        }
+    }
+
+    files
+}
+
+/// The desktop-file ID of `path` relative to `root`, e.g. `kde/foo.desktop` is `kde-foo.desktop`.
+pub fn file_id(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut components = Vec::new();
+
+    for component in relative.components() {
+        components.push(component.as_os_str().to_str()?);
+    }
+
+    Some(components.join("-"))
+}
+
+/// The file in `root` with the desktop-file ID `id`, e.g. `kde/foo.desktop` or `kde-foo.desktop`
+/// for `kde-foo.desktop`.
+pub fn find_id(root: &Path, id: &str) -> Option<PathBuf> {
+    let path = root.join(id);
+
+    if path.is_file() {
+        return Some(path);
+    }
+
+    id.match_indices('-').find_map(|(i, _)| {
+        let dir = root.join(&id[..i]);
+
+        if i > 0 && dir.is_dir() {
+            find_id(&dir, &id[(i + 1)..])
+        } else {
+            None
+        }
+    })
+}
+
+/// `root` and all directories below it, without following symlinked directories.
+pub fn walk_dirs(root: &Path) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+    let mut i = 0;
+
+    while i < dirs.len() {
+        if let Ok(entries) = dirs[i].read_dir() {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                    dirs.push(entry.path());
+                }
+            }
+        }
+
+        i += 1;
+    }
+
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn find_prefixed_id() {
+        let root = env::temp_dir().join(format!("pyroxene-discovery-{}", std::process::id()));
+        fs::create_dir_all(root.join("kde").join("net")).unwrap();
+        fs::write(root.join("kde").join("net").join("foo.desktop"), "").unwrap();
+        fs::write(root.join("bar-baz.desktop"), "").unwrap();
+
+        assert_eq!(
+            find_id(&root, "kde-net-foo.desktop"),
+            Some(root.join("kde").join("net").join("foo.desktop"))
+        );
+        assert_eq!(
+            find_id(&root, "bar-baz.desktop"),
+            Some(root.join("bar-baz.desktop"))
+        );
+        assert_eq!(find_id(&root, "kde-foo.desktop"), None);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn symlink_loops() {
+        let root = env::temp_dir().join(format!("pyroxene-discovery-loop-{}", std::process::id()));
+        fs::create_dir_all(root.join("kde")).unwrap();
+        fs::write(root.join("kde").join("foo.desktop"), "").unwrap();
+        std::os::unix::fs::symlink("..", root.join("kde").join("loop")).unwrap();
+        std::os::unix::fs::symlink("kde/foo.desktop", root.join("bar.desktop")).unwrap();
+
+        let mut ids: Vec<String> = scan_dir(&root, "desktop")
+            .into_iter()
+            .map(|file| file.id)
+            .collect();
+        ids.sort();
+
+        assert_eq!(ids, vec!["bar.desktop", "kde-foo.desktop"]);
+        assert_eq!(walk_dirs(&root), vec![root.clone(), root.join("kde")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,331 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{fmt, io};
+
+use crate::desktop::{DesktopAction, DesktopEntry, DesktopEntryKind};
+
+#[derive(Debug)]
+pub enum ExecErr {
+    Empty,
+    UnterminatedQuote,
+    NotLaunchable,
+    IO(io::Error),
+}
+
+impl fmt::Display for ExecErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecErr::Empty => write!(f, "Exec is empty"),
+            ExecErr::UnterminatedQuote => write!(f, "Exec has an unterminated quote"),
+            ExecErr::NotLaunchable => write!(f, "entry has nothing to launch"),
+            ExecErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ExecErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExecErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Split an `Exec` value into arguments following the quoting rules of the Desktop Entry
+/// Specification.
+pub fn split_exec(exec: &str) -> Result<Vec<String>, ExecErr> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if in_arg {
+                    args.push(current.clone());
+                    current.clear();
+                    in_arg = false;
+                }
+            },
+            '"' => {
+                in_arg = true;
+
+                loop {
+                    match chars.next() {
+                        None => return Err(ExecErr::UnterminatedQuote),
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.next() {
+                                Some(c @ '"') | Some(c @ '`') | Some(c @ '$') | Some(c @ '\\') => {
+                                    current.push(c)
+                                },
+                                Some(c) => {
+                                    current.push('\\');
+                                    current.push(c);
+                                },
+                                None => return Err(ExecErr::UnterminatedQuote),
+                            }
+                        },
+                        Some(c) => current.push(c),
+                    }
+                }
+            },
+            c => {
+                in_arg = true;
+                current.push(c);
+            },
+        }
+    }
+
+    if in_arg {
+        args.push(current);
+    }
+
+    if args.is_empty() {
+        Err(ExecErr::Empty)
+    } else {
+        Ok(args)
+    }
+}
+
+/// Quote an argument for an `Exec` value if needed, the reverse of [`split_exec`].
+pub fn quote_arg(arg: &str) -> String {
+    const RESERVED: &str = " \t\n\"'\\><~|&;$*?#()`";
+
+    if !arg.is_empty() && !arg.contains(|c| RESERVED.contains(c)) {
+        return arg.to_owned();
+    }
+
+    let mut quoted = String::from("\"");
+
+    for c in arg.chars() {
+        if c == '"' || c == '`' || c == '$' || c == '\\' {
+            quoted.push('\\');
+        }
+
+        quoted.push(c);
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Does `Exec` take files or URLs through `%f`, `%F`, `%u` or `%U`?
+pub fn accepts_files(exec: &str) -> bool {
+    ["%f", "%F", "%u", "%U"].iter().any(|c| exec.contains(c))
+}
+
+/// Expand the field codes of `exec` into the command lines to run. More than one command line is
+/// returned when `%f` or `%u` is used with multiple files.
+pub fn expand(
+    entry: &DesktopEntry,
+    exec: &str,
+    files: &[PathBuf],
+) -> Result<Vec<Vec<String>>, ExecErr> {
+    let args = split_exec(exec)?;
+    let single = args.iter().any(|a| a.contains("%f") || a.contains("%u"));
+
+    if single && files.len() > 1 {
+        let mut commands = Vec::with_capacity(files.len());
+
+        for file in files {
+            commands.push(expand_args(entry, &args, std::slice::from_ref(file)));
+        }
+
+        Ok(commands)
+    } else {
+        Ok(vec![expand_args(entry, &args, files)])
+    }
+}
+
+fn expand_args(entry: &DesktopEntry, args: &[String], files: &[PathBuf]) -> Vec<String> {
+    let paths: Vec<String> = files
+        .iter()
+        .map(|f| f.to_string_lossy().into_owned())
+        .collect();
+    let uris: Vec<String> = files.iter().map(|f| file_uri(f)).collect();
+    let mut expanded = Vec::with_capacity(args.len());
+
+    for arg in args {
+        match arg.as_str() {
+            "%F" => expanded.extend(paths.iter().cloned()),
+            "%U" => expanded.extend(uris.iter().cloned()),
+            "%f" | "%u" if files.is_empty() => (),
+            "%i" => {
+                if let Some(icon) = entry.icon.as_ref() {
+                    expanded.push(String::from("--icon"));
+                    expanded.push(icon.clone());
+                }
+            },
+            _ => {
+                let mut out = String::with_capacity(arg.len());
+                let mut chars = arg.chars();
+                let mut only_removed = !arg.is_empty();
+
+                while let Some(c) = chars.next() {
+                    if c != '%' {
+                        out.push(c);
+                        only_removed = false;
+                        continue;
+                    }
+
+                    match chars.next() {
+                        Some('%') => out.push('%'),
+                        Some('f') | Some('F') => {
+                            out.push_str(paths.first().map(|p| p.as_str()).unwrap_or(""))
+                        },
+                        Some('u') | Some('U') => {
+                            out.push_str(uris.first().map(|u| u.as_str()).unwrap_or(""))
+                        },
+                        Some('c') => out.push_str(&entry.name),
+                        Some('k') => out.push_str(&entry.source.to_string_lossy()),
+                        Some('i') => out.push_str(entry.icon.as_deref().unwrap_or("")),
+                        // Deprecated & unknown field codes are removed.
+                        _ => continue,
+                    }
+
+                    only_removed = false;
+                }
+
+                // An argument of nothing but removed field codes is removed with them.
+                if !only_removed {
+                    expanded.push(out);
+                }
+            },
+        }
+    }
+
+    expanded
+}
+
+/// Launch an entry, or one of its actions, with the given files.
+pub fn launch(
+    entry: &DesktopEntry,
+    action: Option<&DesktopAction>,
+    files: &[PathBuf],
+) -> Result<(), ExecErr> {
+    if entry.kind == DesktopEntryKind::Link && action.is_none() {
+        let url = entry.url.as_ref().ok_or(ExecErr::NotLaunchable)?;
+        Command::new("xdg-open")
+            .arg(url)
+            .spawn()
+            .map_err(ExecErr::IO)?;
+        return Ok(());
+    }
+
+    let exec = match action {
+        Some(action) => action.exec.as_str(),
+        None => entry.exec.as_deref().ok_or(ExecErr::NotLaunchable)?,
+    };
+
+    for args in expand(entry, exec, files)? {
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..]);
+
+        if let Some(path) = entry.path.as_ref().filter(|p| !p.is_empty()) {
+            command.current_dir(path);
+        }
+
+        if entry.prefers_non_default_gpu() {
+            command.env("DRI_PRIME", "1");
+        }
+
+        command.spawn().map_err(ExecErr::IO)?;
+    }
+
+    Ok(())
+}
+
+/// A `file://` URI for a path, percent-encoding everything but unreserved characters and `/`.
+pub fn file_uri(path: &Path) -> String {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    };
+
+    let mut uri = String::from("file://");
+
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            },
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desktop::test_entry;
+
+    fn expand_exec(exec: &str, files: &[&str]) -> Vec<Vec<String>> {
+        let entry = test_entry(
+            "viewer.desktop",
+            "/usr/share/applications/viewer.desktop",
+            &format!("Name=Viewer\nIcon=viewer\nExec={}\n", exec),
+        );
+        let files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+        expand(&entry, exec, &files).unwrap()
+    }
+
+    #[test]
+    fn split_quoting() {
+        assert_eq!(
+            split_exec(r#"app  --title "a b" "x\"y\$z\\w" "\q""#).unwrap(),
+            vec!["app", "--title", "a b", r#"x"y$z\w"#, r"\q"]
+        );
+        assert_eq!(split_exec(r#"app """#).unwrap(), vec!["app", ""]);
+        assert!(matches!(split_exec(" \t"), Err(ExecErr::Empty)));
+        assert!(matches!(
+            split_exec(r#"app "open"#),
+            Err(ExecErr::UnterminatedQuote)
+        ));
+    }
+
+    #[test]
+    fn quote_roundtrip() {
+        for arg in &["plain", "", "a b", r#"q"u$o`t\e"#, "semi;colon"] {
+            assert_eq!(split_exec(&quote_arg(arg)).unwrap(), vec![*arg]);
+        }
+    }
+
+    #[test]
+    fn expand_field_codes() {
+        assert_eq!(
+            expand_exec("viewer %F", &["/a", "/b c"]),
+            vec![vec!["viewer", "/a", "/b c"]]
+        );
+        assert_eq!(
+            expand_exec("viewer %U", &["/a b"]),
+            vec![vec!["viewer", "file:///a%20b"]]
+        );
+        assert_eq!(
+            expand_exec("viewer %f", &["/a", "/b"]),
+            vec![vec!["viewer", "/a"], vec!["viewer", "/b"]]
+        );
+        assert_eq!(expand_exec("viewer %f %u", &[]), vec![vec!["viewer"]]);
+        assert_eq!(
+            expand_exec("viewer %i --name=%c %% %d", &[]),
+            vec![vec!["viewer", "--icon", "viewer", "--name=Viewer", "%"]]
+        );
+        assert_eq!(
+            expand_exec("viewer %n%m --debug%v", &[]),
+            vec![vec!["viewer", "--debug"]]
+        );
+        assert_eq!(expand_exec(r#"viewer """#, &[]), vec![vec!["viewer", ""]]);
+        assert_eq!(
+            expand_exec("viewer --file=%f", &["/a"]),
+            vec![vec!["viewer", "--file=/a"]]
+        );
+    }
+}
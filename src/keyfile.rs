@@ -0,0 +1,554 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::{fmt, io};
+
+use crate::locale::{Locale, LocalizedValue};
+
+/// A parsed INI-like XDG key file, e.g. a desktop entry, `.directory` or `mimeapps.list`.
+/// Comments, blank lines & untouched entries are kept as written so the file can be saved without
+/// changing anything but the edits.
+#[derive(Clone, Debug, Default)]
+pub struct KeyFile {
+    pub groups: Vec<Group>,
+    /// Comments & blank lines after the last entry.
+    trailing: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Group {
+    pub name: String,
+    /// The line of the header, `0` for groups that were added.
+    pub line: usize,
+    pub entries: Vec<KeyEntry>,
+    /// The header line as written, including the line ending.
+    raw: Option<String>,
+    /// Comments & blank lines before the header.
+    leading: Vec<String>,
+    /// Comments & blank lines that were before removed entries at the end of the group.
+    trailing: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyEntry {
+    pub key: String,
+    pub locale: Option<String>,
+    /// The value as written in the file, before escape sequences are decoded.
+    pub value: String,
+    /// The line of the entry, `0` for entries that were added.
+    pub line: usize,
+    /// The line as written, including the line ending. `None` once the value is changed.
+    raw: Option<String>,
+    /// Comments & blank lines before the entry.
+    leading: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum KeyFileErr {
+    InvalidGroupHeader(usize),
+    InvalidEntry(usize),
+    EntryOutsideGroup(usize),
+    InvalidBoolean(usize, String),
+    IO(io::Error),
+}
+
+impl fmt::Display for KeyFileErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyFileErr::InvalidGroupHeader(line) => {
+                write!(f, "line {}: invalid group header", line)
+            },
+            KeyFileErr::InvalidEntry(line) => write!(f, "line {}: expected Key=Value", line),
+            KeyFileErr::EntryOutsideGroup(line) => {
+                write!(f, "line {}: entry before the first group header", line)
+            },
+            KeyFileErr::InvalidBoolean(line, key) => {
+                write!(f, "line {}: {} is not a boolean", line, key)
+            },
+            KeyFileErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for KeyFileErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KeyFileErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl KeyFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, KeyFileErr> {
+        let mut handle = File::open(path).map_err(KeyFileErr::IO)?;
+        let mut buffer = String::new();
+        handle
+            .read_to_string(&mut buffer)
+            .map_err(KeyFileErr::IO)?;
+        Self::parse(&buffer)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, KeyFileErr> {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut pending = Vec::new();
+
+        for (i, raw) in text.split_inclusive('\n').enumerate() {
+            let line_num = i + 1;
+            let line = raw.strip_suffix('\n').unwrap_or(raw);
+            let line = line.strip_suffix('\r').unwrap_or(line).trim_start();
+
+            if line.is_empty() || line.starts_with('#') {
+                pending.push(raw.to_owned());
+                continue;
+            }
+
+            if line.starts_with('[') {
+                let line = line.trim_end();
+
+                if line.len() < 3
+                    || !line.ends_with(']')
+                    || line[1..(line.len() - 1)].contains(['[', ']'])
+                {
+                    return Err(KeyFileErr::InvalidGroupHeader(line_num));
+                }
+
+                groups.push(Group {
+                    name: line[1..(line.len() - 1)].to_owned(),
+                    line: line_num,
+                    entries: Vec::new(),
+                    raw: Some(raw.to_owned()),
+                    leading: pending.split_off(0),
+                    trailing: Vec::new(),
+                });
+
+                continue;
+            }
+
+            let split = line.find('=').ok_or(KeyFileErr::InvalidEntry(line_num))?;
+            let key = line[..split].trim_end();
+            let value = line[(split + 1)..].trim_start();
+
+            let (key, locale) = match key.find('[') {
+                Some(start) => {
+                    if !key.ends_with(']') || start + 2 > key.len() - 1 {
+                        return Err(KeyFileErr::InvalidEntry(line_num));
+                    }
+
+                    (
+                        &key[..start],
+                        Some(key[(start + 1)..(key.len() - 1)].to_owned()),
+                    )
+                },
+                None => (key, None),
+            };
+
+            if key.is_empty() {
+                return Err(KeyFileErr::InvalidEntry(line_num));
+            }
+
+            groups
+                .last_mut()
+                .ok_or(KeyFileErr::EntryOutsideGroup(line_num))?
+                .entries
+                .push(KeyEntry {
+                    key: key.to_owned(),
+                    locale,
+                    value: value.to_owned(),
+                    line: line_num,
+                    raw: Some(raw.to_owned()),
+                    leading: pending.split_off(0),
+                });
+        }
+
+        Ok(KeyFile {
+            groups,
+            trailing: pending,
+        })
+    }
+
+    /// The file as text. Untouched lines are written exactly as they were parsed.
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+
+        let push_line = |out: &mut String, line: &str| {
+            if !out.is_empty() && !out.ends_with('\n') {
+                out.push('\n');
+            }
+
+            out.push_str(line);
+        };
+
+        for group in self.groups.iter() {
+            group.leading.iter().for_each(|l| push_line(&mut out, l));
+
+            match group.raw.as_ref() {
+                Some(raw) => push_line(&mut out, raw),
+                None => push_line(&mut out, &format!("[{}]\n", group.name)),
+            }
+
+            for entry in group.entries.iter() {
+                entry.leading.iter().for_each(|l| push_line(&mut out, l));
+
+                match entry.raw.as_ref() {
+                    Some(raw) => push_line(&mut out, raw),
+                    None => {
+                        let line = match entry.locale.as_ref() {
+                            Some(locale) => format!("{}[{}]={}\n", entry.key, locale, entry.value),
+                            None => format!("{}={}\n", entry.key, entry.value),
+                        };
+
+                        push_line(&mut out, &line);
+                    },
+                }
+            }
+
+            group.trailing.iter().for_each(|l| push_line(&mut out, l));
+        }
+
+        self.trailing.iter().for_each(|l| push_line(&mut out, l));
+        out
+    }
+
+    /// Write the file to `path`, replacing it atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), KeyFileErr> {
+        let path = path.as_ref();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(KeyFileErr::IO)?;
+        }
+
+        let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        fs::write(&tmp_path, self.serialize()).map_err(KeyFileErr::IO)?;
+        fs::rename(tmp_path, path).map_err(KeyFileErr::IO)
+    }
+
+    /// The first group named `name`.
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut Group> {
+        self.groups.iter_mut().find(|g| g.name == name)
+    }
+
+    /// The first group named `name`, appending an empty one if there is none.
+    pub fn group_or_insert(&mut self, name: &str) -> &mut Group {
+        match self.groups.iter().position(|g| g.name == name) {
+            Some(i) => &mut self.groups[i],
+            None => {
+                let mut leading = std::mem::take(&mut self.trailing);
+
+                // Separate the new group from the previous one like most key files do.
+                if !self.groups.is_empty()
+                    && leading.last().map(|l| !l.trim().is_empty()).unwrap_or(true)
+                {
+                    leading.push(String::from("\n"));
+                }
+
+                self.groups.push(Group {
+                    name: name.to_owned(),
+                    line: 0,
+                    entries: Vec::new(),
+                    raw: None,
+                    leading,
+                    trailing: Vec::new(),
+                });
+
+                self.groups.last_mut().unwrap()
+            },
+        }
+    }
+}
+
+impl Group {
+    /// The last unlocalized entry for `key`.
+    pub fn entry(&self, key: &str) -> Option<&KeyEntry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.key == key && e.locale.is_none())
+    }
+
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.entry(key).map(|e| e.value.as_str())
+    }
+
+    pub fn string(&self, key: &str) -> Option<String> {
+        self.raw(key).map(decode_string)
+    }
+
+    pub fn list(&self, key: &str) -> Option<Vec<String>> {
+        self.raw(key).map(decode_list)
+    }
+
+    pub fn boolean(&self, key: &str) -> Result<Option<bool>, KeyFileErr> {
+        match self.entry(key) {
+            None => Ok(None),
+            Some(entry) => {
+                match entry.value.as_str() {
+                    "true" | "1" => Ok(Some(true)),
+                    "false" | "0" => Ok(Some(false)),
+                    _ => Err(KeyFileErr::InvalidBoolean(entry.line, entry.key.clone())),
+                }
+            },
+        }
+    }
+
+    /// The localized variants of `key` with the best match for `locale`.
+    pub fn localized<T, F: Fn(&str) -> T>(
+        &self,
+        key: &str,
+        locale: Option<&Locale>,
+        decode: F,
+    ) -> LocalizedValue<T> {
+        let mut value = LocalizedValue::default();
+
+        for entry in self.entries.iter().filter(|e| e.key == key) {
+            value.set(locale, entry.locale.as_deref(), decode(&entry.value));
+        }
+
+        value
+    }
+
+    pub fn localized_string(&self, key: &str, locale: Option<&Locale>) -> LocalizedValue<String> {
+        self.localized(key, locale, decode_string)
+    }
+
+    pub fn localized_list(
+        &self,
+        key: &str,
+        locale: Option<&Locale>,
+    ) -> LocalizedValue<Vec<String>> {
+        self.localized(key, locale, decode_list)
+    }
+
+    /// Set the raw value of `key`, or of its `locale` variant. The last existing entry is changed,
+    /// otherwise a new entry is appended.
+    pub fn set_raw(&mut self, key: &str, locale: Option<&str>, value: String) {
+        match self
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|e| e.key == key && e.locale.as_deref() == locale)
+        {
+            Some(entry) => {
+                if entry.value != value {
+                    entry.value = value;
+                    entry.raw = None;
+                }
+            },
+            None => {
+                self.entries.push(KeyEntry {
+                    key: key.to_owned(),
+                    locale: locale.map(|l| l.to_owned()),
+                    value,
+                    line: 0,
+                    raw: None,
+                    leading: Vec::new(),
+                });
+            },
+        }
+    }
+
+    pub fn set_string(&mut self, key: &str, value: &str) {
+        self.set_raw(key, None, encode_string(value));
+    }
+
+    pub fn set_list<S: AsRef<str>>(&mut self, key: &str, values: &[S]) {
+        self.set_raw(key, None, encode_list(values));
+    }
+
+    pub fn set_boolean(&mut self, key: &str, value: bool) {
+        self.set_raw(key, None, value.to_string());
+    }
+
+    /// Remove the entries `keep` returns `false` for. Comments before removed entries are kept.
+    pub fn retain<F: Fn(&KeyEntry) -> bool>(&mut self, keep: F) {
+        let mut leading = Vec::new();
+        let mut entries = Vec::with_capacity(self.entries.len());
+
+        for mut entry in self.entries.drain(..) {
+            if !keep(&entry) {
+                leading.append(&mut entry.leading);
+            } else {
+                entry.leading.splice(0..0, leading.drain(..));
+                entries.push(entry);
+            }
+        }
+
+        self.entries = entries;
+        leading.append(&mut self.trailing);
+        self.trailing = leading;
+    }
+}
+
+/// Decode the `\s`, `\n`, `\t`, `\r` & `\\` escapes of a string value. Unknown escapes are kept.
+pub fn decode_string(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            },
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// Escape a string value, the reverse of [`decode_string`].
+pub fn encode_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            // Leading spaces would be trimmed when parsing.
+            ' ' if i == 0 => out.push_str("\\s"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Escape & join a list with a trailing `;`, the reverse of [`decode_list`].
+pub fn encode_list<S: AsRef<str>>(values: &[S]) -> String {
+    let mut out = String::new();
+
+    for value in values {
+        out.push_str(&encode_string(value.as_ref()).replace(';', "\\;"));
+        out.push(';');
+    }
+
+    out
+}
+
+/// Split a `;` separated list honoring `\;`, then decode each value. Empty values are dropped.
+pub fn decode_list(raw: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                if !current.is_empty() {
+                    values.push(decode_string(&current));
+                    current.clear();
+                }
+            },
+            '\\' => {
+                match chars.next() {
+                    Some(';') => current.push(';'),
+                    Some(other) => {
+                        current.push('\\');
+                        current.push(other);
+                    },
+                    None => current.push('\\'),
+                }
+            },
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        values.push(decode_string(&current));
+    }
+
+    values
+}
+
+/// The first escape sequence of `raw` that isn't defined for strings, or for lists if `list`.
+pub fn invalid_escape(raw: &str, list: bool) -> Option<String> {
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('s') | Some('n') | Some('t') | Some('r') | Some('\\') => (),
+                Some(';') if list => (),
+                Some(other) => return Some(format!("\\{}", other)),
+                None => return Some(String::from("\\")),
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_escapes() {
+        assert_eq!(decode_string(r"a\sb\nc\td\re\\f"), "a b\nc\td\re\\f");
+        assert_eq!(decode_string(r"\\s"), r"\s");
+    }
+
+    #[test]
+    fn unknown_escapes_are_kept() {
+        assert_eq!(decode_string(r"C:\q"), r"C:\q");
+        assert_eq!(decode_string(r"trailing\"), r"trailing\");
+        assert_eq!(invalid_escape(r"a\qb", false), Some(String::from(r"\q")));
+        assert_eq!(invalid_escape(r"a\;b", false), Some(String::from(r"\;")));
+        assert_eq!(invalid_escape(r"a\;b", true), None);
+    }
+
+    #[test]
+    fn list_separators() {
+        assert_eq!(decode_list("a;b;"), vec!["a", "b"]);
+        assert_eq!(decode_list("a;b"), vec!["a", "b"]);
+        assert_eq!(decode_list(r"a\;b;c;"), vec!["a;b", "c"]);
+        assert_eq!(decode_list(r"a\\;b;"), vec![r"a\", "b"]);
+        assert_eq!(decode_list(r"a\sb;\q;"), vec!["a b", r"\q"]);
+        assert_eq!(decode_list(";;"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let values = vec![" lead", "semi;colon", "back\\slash", "new\nline"];
+        assert_eq!(decode_list(&encode_list(&values)), values);
+        assert_eq!(decode_string(&encode_string(" a\tb")), " a\tb");
+    }
+
+    #[test]
+    fn parse_groups_and_locales() {
+        let text = "# comment\n[Desktop Entry]\nName=Files\nName[de]=Dateien\n";
+        let key_file = KeyFile::parse(text).unwrap();
+        let group = key_file.group("Desktop Entry").unwrap();
+
+        assert_eq!(group.string("Name").as_deref(), Some("Files"));
+        assert_eq!(key_file.serialize(), text);
+        assert!(matches!(
+            KeyFile::parse("Name=Files\n"),
+            Err(KeyFileErr::EntryOutsideGroup(1))
+        ));
+        assert!(matches!(
+            KeyFile::parse("[Desktop Entry\n"),
+            Err(KeyFileErr::InvalidGroupHeader(1))
+        ));
+    }
+}
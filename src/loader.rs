@@ -0,0 +1,379 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::appstream::Catalog;
+use crate::cache::{self, EntryCache};
+use crate::config::Config;
+use crate::desktop::{DesktopEntry, DesktopEntryErr, DesktopEntryKind};
+use crate::discovery::{self, DesktopFile};
+use crate::watch::Event;
+use crate::{appimage, steam};
+
+/// How long parsed entries are collected before they are passed on together.
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The number of threads parsing desktop files.
+fn worker_count() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+/// Discover & parse every entry, passing them to `on_batch` in batches as they are parsed.
+/// Entries that shouldn't be shown are skipped, as are `Type=Directory` entries, which only name
+/// categories from the `desktop-directories`, see [`crate::directory::apply`]. The desktop files
+/// of AppImages are parsed as they are extracted, installed Steam games without a desktop file of
+/// their own come last. Entries get the AppStream metadata of their application from `catalog`
+/// once it is loaded. Unchanged files are taken from the cache.
+pub fn load<F: FnMut(Vec<DesktopEntry>)>(config: &Config, catalog: &Catalog, mut on_batch: F) {
+    let mut cache = if config.no_cache {
+        EntryCache::default()
+    } else {
+        EntryCache::load()
+    };
+
+    let mut files = cache.desktop_files();
+    let mut ids: HashSet<String> = files.iter().map(|file| file.id.clone()).collect();
+    let application_dirs = discovery::application_dirs();
+
+    // The application directories take precedence over the other directories of the menu file.
+    for dir in config.app_dirs.iter() {
+        if !dir.is_dir() || application_dirs.contains(dir) {
+            continue;
+        }
+
+        for file in discovery::scan_dir(dir, "desktop") {
+            if ids.insert(file.id.clone()) {
+                files.push(file);
+            }
+        }
+    }
+
+    let cache = Arc::new(cache);
+    let (file_sender, file_receiver) = mpsc::channel::<DesktopFile>();
+    let queue = Arc::new(Mutex::new(file_receiver));
+    let (sender, receiver) = mpsc::channel();
+
+    for _ in 0..worker_count() {
+        let cache = cache.clone();
+        let queue = queue.clone();
+        let sender = sender.clone();
+        let desktops = config.desktops.clone();
+        let show_missing = config.show_missing;
+
+        thread::spawn(move || {
+            loop {
+                let file = match queue.lock().unwrap().recv() {
+                    Ok(ok) => ok,
+                    Err(_) => break,
+                };
+
+                if let Some(entry) = parse(&cache, &file, &desktops, show_missing) {
+                    if sender.send(entry).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    drop(sender);
+
+    for file in files {
+        let _ = file_sender.send(file);
+    }
+
+    // The workers stop once the AppImages are extracted and the queue is empty.
+    let appimage_dirs = config.appimage_dirs.clone();
+
+    thread::spawn(move || {
+        appimage::desktop_files(&appimage_dirs, |file| {
+            let _ = file_sender.send(file);
+        })
+    });
+
+    let mut linked = HashSet::new();
+    let mut batch = Vec::new();
+    let mut last_batch = Instant::now();
+
+    loop {
+        let timeout = BATCH_INTERVAL
+            .checked_sub(last_batch.elapsed())
+            .unwrap_or_default();
+
+        match receiver.recv_timeout(timeout) {
+            Ok(mut entry) => {
+                entry.appstream = catalog.clone();
+
+                if let Some(app_id) = steam::app_id(&entry) {
+                    linked.insert(app_id.to_owned());
+                }
+
+                batch.push(entry);
+            },
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if last_batch.elapsed() >= BATCH_INTERVAL {
+            if !batch.is_empty() {
+                on_batch(batch.split_off(0));
+            }
+
+            last_batch = Instant::now();
+        }
+    }
+
+    if !config.no_cache {
+        if let Err(e) = cache.save() {
+            println!(
+                "Warning: Failed to write cache: {:?} ({})",
+                cache::cache_path(),
+                e
+            );
+        }
+    }
+
+    for game in steam::installed_games() {
+        if linked.contains(&game.app_id) {
+            continue;
+        }
+
+        match game.desktop_entry() {
+            Ok(ok) => batch.push(ok),
+            Err(e) => println!("Failed to add Steam game {}: {}", game.name, e),
+        }
+    }
+
+    if !batch.is_empty() {
+        on_batch(batch);
+    }
+}
+
+/// Parse a desktop file, `None` if it failed or shouldn't be shown.
+fn parse(
+    cache: &EntryCache,
+    file: &DesktopFile,
+    desktops: &[String],
+    show_missing: bool,
+) -> Option<DesktopEntry> {
+    shown(cache.entry(file, desktops), file, show_missing)
+}
+
+/// The entry if it should be shown. Failures are reported.
+fn shown(
+    result: Result<DesktopEntry, DesktopEntryErr>,
+    file: &DesktopFile,
+    show_missing: bool,
+) -> Option<DesktopEntry> {
+    let result = match result {
+        Err(DesktopEntryErr::TryExec(entry)) if show_missing => Ok(*entry),
+        result => result,
+    };
+
+    match result {
+        Ok(ok) => {
+            if ok.kind == DesktopEntryKind::Directory {
+                None
+            } else {
+                Some(ok)
+            }
+        },
+        Err(e) => {
+            match e {
+                DesktopEntryErr::UnsupportedType(_)
+                | DesktopEntryErr::Hidden
+                | DesktopEntryErr::OnlyShowIn
+                | DesktopEntryErr::NotShowIn
+                | DesktopEntryErr::TryExec(_) => None,
+                e => {
+                    println!(
+                        "Failed to parse desktop file: {}: {}",
+                        file.path.display(),
+                        e
+                    );
+                    None
+                },
+            }
+        },
+    }
+}
+
+/// The entries that changed after files changed.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// The desktop-file IDs whose entries changed, were added or removed.
+    pub ids: Vec<String>,
+    /// The current entries of those IDs that should be shown.
+    pub entries: Vec<DesktopEntry>,
+}
+
+/// Knows which file each desktop-file ID was loaded from, so only the entries of changed files
+/// have to be parsed again.
+pub struct Reloader {
+    /// The application directories followed by the other directories of the menu file.
+    roots: Vec<PathBuf>,
+    appimage_dirs: Vec<PathBuf>,
+    /// The file of each desktop-file ID, for AppImages the AppImage itself.
+    files: HashMap<String, PathBuf>,
+}
+
+impl Reloader {
+    pub fn new(config: &Config) -> Self {
+        let mut roots = discovery::application_dirs();
+
+        for dir in config.app_dirs.iter() {
+            if !roots.contains(dir) {
+                roots.push(dir.clone());
+            }
+        }
+
+        let mut reloader = Reloader {
+            roots,
+            appimage_dirs: config.appimage_dirs.clone(),
+            files: HashMap::new(),
+        };
+
+        for root in reloader.roots.iter().filter(|root| root.is_dir()) {
+            for file in discovery::scan_dir(root, "desktop") {
+                reloader.files.entry(file.id).or_insert(file.path);
+            }
+        }
+
+        for appimage in appimage::appimages(&reloader.appimage_dirs) {
+            reloader
+                .files
+                .entry(appimage::desktop_id(&appimage))
+                .or_insert(appimage);
+        }
+
+        reloader
+    }
+
+    /// Parse the files of the IDs affected by `events` again, see [`watch::run`]. A file may
+    /// shadow or stop shadowing a file with the same ID in a less important directory. `None`
+    /// if events were lost and everything has to be loaded again.
+    pub fn reload(
+        &mut self,
+        config: &Config,
+        catalog: &Catalog,
+        events: &[Event],
+    ) -> Option<Changes> {
+        let mut ids = HashSet::new();
+        let mut appimages_changed = false;
+
+        for event in events {
+            if event.overflow {
+                return None;
+            }
+
+            if self
+                .appimage_dirs
+                .iter()
+                .any(|dir| event.path.parent() == Some(dir.as_path()))
+            {
+                ids.insert(appimage::desktop_id(&event.path));
+                appimages_changed = true;
+                continue;
+            }
+
+            if !event.is_dir {
+                if event.path.extension().map(|e| e == "desktop") == Some(true) {
+                    ids.extend(
+                        self.roots
+                            .iter()
+                            .find_map(|root| discovery::file_id(root, &event.path)),
+                    );
+                }
+
+                continue;
+            }
+
+            // A directory appeared or disappeared, the entries below it did too.
+            ids.extend(
+                self.files
+                    .iter()
+                    .filter(|(_, path)| path.starts_with(&event.path))
+                    .map(|(id, _)| id.clone()),
+            );
+
+            for root in self.roots.iter() {
+                let dir = if root.starts_with(&event.path) {
+                    root
+                } else if event.path.starts_with(root) {
+                    &event.path
+                } else {
+                    continue;
+                };
+
+                if dir.is_dir() {
+                    ids.extend(
+                        discovery::scan_dir(dir, "desktop")
+                            .into_iter()
+                            .filter_map(|file| discovery::file_id(root, &file.path)),
+                    );
+                }
+            }
+
+            for dir in self.appimage_dirs.iter() {
+                if dir.starts_with(&event.path) {
+                    appimages_changed = true;
+                    ids.extend(
+                        appimage::appimages(std::slice::from_ref(dir))
+                            .iter()
+                            .map(|appimage| appimage::desktop_id(appimage)),
+                    );
+                }
+            }
+        }
+
+        let mut changes = Changes::default();
+
+        for id in ids {
+            self.files.remove(&id);
+
+            if let Some((path, file)) = self.resolve(&id) {
+                self.files.insert(id.clone(), path);
+                let result = DesktopEntry::with_id(file.id.clone(), &file.path, &config.desktops);
+
+                if let Some(mut entry) = shown(result, &file, config.show_missing) {
+                    entry.appstream = catalog.clone();
+                    changes.entries.push(entry);
+                }
+            }
+
+            changes.ids.push(id);
+        }
+
+        if appimages_changed {
+            appimage::prune(&self.appimage_dirs);
+        }
+
+        Some(changes)
+    }
+
+    /// The file providing a desktop-file ID & the desktop file to parse, which differ for
+    /// AppImages.
+    fn resolve(&self, id: &str) -> Option<(PathBuf, DesktopFile)> {
+        for root in self.roots.iter() {
+            if let Some(path) = discovery::find_id(root, id) {
+                let file = DesktopFile {
+                    id: id.to_owned(),
+                    path: path.clone(),
+                };
+
+                return Some((path, file));
+            }
+        }
+
+        let appimage = appimage::appimages(&self.appimage_dirs)
+            .into_iter()
+            .find(|appimage| appimage::desktop_id(appimage) == id)?;
+        let file = appimage::desktop_file(&appimage)?;
+        Some((appimage, file))
+    }
+}
@@ -0,0 +1,187 @@
+use std::env;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locale {
+    pub lang: String,
+    pub country: Option<String>,
+    pub modifier: Option<String>,
+}
+
+impl Locale {
+    /// The messages locale from `LC_ALL`, `LC_MESSAGES` or `LANG`, in that order.
+    pub fn from_env() -> Option<Self> {
+        for var in &["LC_ALL", "LC_MESSAGES", "LANG"] {
+            if let Ok(value) = env::var(var) {
+                if !value.is_empty() {
+                    return Self::parse(&value);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Parse a `lang_COUNTRY.ENCODING@MODIFIER` string. The encoding is discarded.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (value, modifier) = match value.find('@') {
+            Some(i) => (&value[..i], Some(value[(i + 1)..].to_owned())),
+            None => (value, None),
+        };
+
+        let value = match value.find('.') {
+            Some(i) => &value[..i],
+            None => value,
+        };
+
+        let (lang, country) = match value.find('_') {
+            Some(i) => (&value[..i], Some(value[(i + 1)..].to_owned())),
+            None => (value, None),
+        };
+
+        if lang.is_empty() || lang == "C" || lang == "POSIX" {
+            return None;
+        }
+
+        Some(Locale {
+            lang: lang.to_owned(),
+            country: country.filter(|c| !c.is_empty()),
+            modifier: modifier.filter(|m| !m.is_empty()),
+        })
+    }
+
+    /// Locale keys that match this locale, most specific first.
+    pub fn candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::with_capacity(4);
+
+        if let (Some(country), Some(modifier)) = (self.country.as_ref(), self.modifier.as_ref()) {
+            candidates.push(format!("{}_{}@{}", self.lang, country, modifier));
+        }
+
+        if let Some(country) = self.country.as_ref() {
+            candidates.push(format!("{}_{}", self.lang, country));
+        }
+
+        if let Some(modifier) = self.modifier.as_ref() {
+            candidates.push(format!("{}@{}", self.lang, modifier));
+        }
+
+        candidates.push(self.lang.clone());
+        candidates
+    }
+
+    /// How well the locale of a key matches, lower is better. `None` if it doesn't match at all.
+    pub fn match_level(&self, key_locale: &str) -> Option<usize> {
+        let key_locale = Self::parse(key_locale)?.key();
+        self.candidates().into_iter().position(|c| c == key_locale)
+    }
+
+    /// The locale as it would appear in a key, e.g. `Name[sr_YU@Latn]`.
+    pub fn key(&self) -> String {
+        let mut key = self.lang.clone();
+
+        if let Some(country) = self.country.as_ref() {
+            key.push('_');
+            key.push_str(country);
+        }
+
+        if let Some(modifier) = self.modifier.as_ref() {
+            key.push('@');
+            key.push_str(modifier);
+        }
+
+        key
+    }
+}
+
+/// Selects the best value of a localized key as its variants are encountered.
+#[derive(Clone, Debug)]
+pub struct LocalizedValue<T> {
+    pub default: Option<T>,
+    localized: Option<(usize, T)>,
+}
+
+impl<T> Default for LocalizedValue<T> {
+    fn default() -> Self {
+        LocalizedValue {
+            default: None,
+            localized: None,
+        }
+    }
+}
+
+impl<T> LocalizedValue<T> {
+    pub fn set(&mut self, locale: Option<&Locale>, key_locale: Option<&str>, value: T) {
+        match key_locale {
+            None => self.default = Some(value),
+            Some(key_locale) => {
+                if let Some(level) = locale.and_then(|l| l.match_level(key_locale)) {
+                    if self
+                        .localized
+                        .as_ref()
+                        .map(|(l, _)| level < *l)
+                        .unwrap_or(true)
+                    {
+                        self.localized = Some((level, value));
+                    }
+                }
+            },
+        }
+    }
+
+    /// The best matching localized value, falling back to the untranslated one.
+    pub fn into_best(self) -> Option<T> {
+        match self.localized {
+            Some((_, value)) => Some(value),
+            None => self.default,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Locale::parse("sr_YU.UTF-8@Latn"),
+            Some(Locale {
+                lang: String::from("sr"),
+                country: Some(String::from("YU")),
+                modifier: Some(String::from("Latn")),
+            })
+        );
+        assert_eq!(Locale::parse("C.UTF-8"), None);
+        assert_eq!(Locale::parse("POSIX"), None);
+        assert_eq!(Locale::parse(""), None);
+    }
+
+    #[test]
+    fn candidates() {
+        let candidates = |value| Locale::parse(value).unwrap().candidates();
+
+        assert_eq!(
+            candidates("sr_YU@Latn"),
+            vec!["sr_YU@Latn", "sr_YU", "sr@Latn", "sr"]
+        );
+        assert_eq!(candidates("de_DE.UTF-8"), vec!["de_DE", "de"]);
+        assert_eq!(candidates("sr@Latn"), vec!["sr@Latn", "sr"]);
+        assert_eq!(candidates("fr"), vec!["fr"]);
+    }
+
+    #[test]
+    fn best_value() {
+        let locale = Locale::parse("de_AT.UTF-8");
+        let mut value = LocalizedValue::default();
+        value.set(locale.as_ref(), None, "Files");
+        value.set(locale.as_ref(), Some("fr"), "Fichiers");
+        value.set(locale.as_ref(), Some("de"), "Dateien");
+        value.set(locale.as_ref(), Some("de_CH"), "Dateien (CH)");
+        assert_eq!(value.into_best(), Some("Dateien"));
+
+        let mut value = LocalizedValue::default();
+        value.set(None, None, "Files");
+        value.set(None, Some("de"), "Dateien");
+        assert_eq!(value.into_best(), Some("Files"));
+    }
+}
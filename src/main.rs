@@ -1,22 +1,104 @@
 extern crate basalt;
+extern crate flate2;
+extern crate libc;
 extern crate shellexpand;
 extern crate strsim;
+extern crate xml;
 
+pub mod appimage;
+pub mod appstream;
+pub mod autostart;
+pub mod cache;
 pub mod category;
+pub mod config;
 pub mod desktop;
+pub mod directory;
+pub mod discovery;
+pub mod exec;
+pub mod keyfile;
+pub mod loader;
+pub mod locale;
 pub mod menu;
+pub mod menufile;
+pub mod mime;
+pub mod mimeapps;
+pub mod overrides;
+pub mod squashfs;
+pub mod steam;
+pub mod validate;
+pub mod vdf;
+pub mod watch;
+pub mod xdg;
 
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
+use std::{process, thread};
 
+use appstream::Catalog;
 use basalt::input::{InputHookRes, Qwerty};
 use basalt::Basalt;
-use category::Category;
-use desktop::{DesktopEntry, DesktopEntryErr};
+use category::{Category, Rule};
+use config::{Command, Config};
+use desktop::{DesktopEntry, DesktopEntryKind, Origin};
+use loader::Reloader;
 use menu::Menu;
+use menufile::MenuFile;
+use mime::MimeDatabase;
+use mimeapps::MimeApps;
 
 fn main() {
+    let config = match Config::from_args() {
+        Ok(ok) => ok,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        },
+    };
+
+    match config.command.clone() {
+        Command::Validate(files) => {
+            if !validate::run(&files) {
+                process::exit(1);
+            }
+        },
+        Command::Default(mime, id) => {
+            if !mimeapps::run(&mime, id.as_deref(), &config.desktops) {
+                process::exit(1);
+            }
+        },
+        Command::Override(id, edits) => {
+            if !overrides::run(&id, &edits) {
+                process::exit(1);
+            }
+        },
+        Command::Autostart(dry_run) => {
+            if !autostart::run(&config.desktops, dry_run) {
+                process::exit(1);
+            }
+        },
+        Command::Menu | Command::Open(_) => run_menu(config),
+    }
+}
+
+fn run_menu(mut config: Config) {
+    let categories = match &config.command {
+        Command::Open(_) => Vec::new(),
+        _ => {
+            let mut categories = match MenuFile::load() {
+                Some(menu_file) if !menu_file.categories.is_empty() => {
+                    config.app_dirs = menu_file.app_dirs;
+                    menu_file.categories
+                },
+                _ => menu_categories(),
+            };
+
+            directory::apply_all(&mut categories);
+            categories
+        },
+    };
+
     Basalt::initialize(
         basalt::Options::default()
             .ignore_dpi(true)
@@ -37,102 +119,236 @@ fn main() {
                 }),
             );
 
-            let search_dirs = vec![
-                PathBuf::from("/usr/share/applications/"),
-                shellexpand::tilde("~/.local/share/applications/")
-                    .into_owned()
-                    .into(),
-            ];
-
-            let mut categories = vec![
-                Category::new("utility", "Accessories", "applications-utilities"),
-                Category::new("development", "Development", "applications-development"),
-                Category::new("education", "Education", "applications-science"),
-                Category::new("game", "Games", "applications-games"),
-                Category::new("graphics", "Graphics", "applications-graphics"),
-                Category::new("audiovideo", "Multimedia", "applications-multimedia"),
-                Category::new("network", "Network", "applications-internet"),
-                Category::new("office", "Office", "applications-office"),
-                Category::new("other", "Other", "applications-other"),
-                Category::new("settings", "Settings", "applications-accessories"),
-                Category::new("system", "System", "applications-system"),
-            ];
-
-            let mut files = Vec::new();
-
-            for search_dir in &search_dirs {
-                if search_dir.exists() {
-                    match search_dir.read_dir() {
-                        Err(e) => {
-                            println!(
-                                "Warning: Failed to read directory: {:?} ({})",
-                                search_dir, e
-                            )
-                        },
-                        Ok(entries) => {
-                            for entry_result in entries {
-                                match entry_result {
-                                    Err(e) => {
-                                        println!("Warning: Failed to read directory entry: {}", e)
-                                    },
-                                    Ok(entry) => {
-                                        let entry_path = entry.path();
-
-                                        if entry_path.is_file() {
-                                            files.push(entry_path);
-                                        }
-                                    },
-                                }
+            let menu = match &config.command {
+                Command::Open(files) => {
+                    let menu = Menu::new(basalt.clone(), Vec::new(), files.clone());
+                    menu.display_all();
+                    menu
+                },
+                _ => Menu::new(basalt.clone(), categories.clone(), Vec::new()),
+            };
+
+            println!(
+                "Launched in {} ms!",
+                start.elapsed().as_micros() as f32 / 1000.0
+            );
+
+            let config = config.clone();
+            let categories = categories.clone();
+
+            // The menu fills in as the entries are parsed, and is kept up to date afterwards.
+            thread::spawn(move || {
+                let start = Instant::now();
+                let mut loaded = 0;
+                let catalog = Catalog::default();
+                let catalog_cp = catalog.clone();
+                let no_cache = config.no_cache;
+                thread::spawn(move || catalog_cp.set(appstream::load(no_cache)));
+
+                match (&config.command, config.prefer) {
+                    (Command::Menu, None) => {
+                        loader::load(&config, &catalog, |batch| {
+                            let entries = menu_entries(batch, &categories, &config);
+                            loaded += entries.len();
+                            menu.add_entries(entries);
+                        });
+                    },
+                    // Which entries to drop or how to rank them is only known once all of them
+                    // are parsed.
+                    _ => {
+                        let entries = load_entries(&config, &categories, &catalog);
+
+                        if let Command::Open(_) = &config.command {
+                            if entries.is_empty() {
+                                println!("No application can open the given files.");
                             }
-                        },
-                    }
+                        }
+
+                        loaded = entries.len();
+                        menu.add_entries(entries);
+                    },
                 }
-            }
 
-            let entries: Vec<_> = files
-                .into_iter()
-                .filter_map(|file| {
-                    match DesktopEntry::new(&file) {
-                        Ok(ok) => {
-                            if categories.iter().any(|c| {
-                                ok.categories
-                                    .iter()
-                                    .map(|c2| c2.to_lowercase())
-                                    .any(|c2| c2 == c.iden)
-                            }) {
-                                Some(Arc::new(ok))
-                            } else {
-                                None
-                            }
+                println!(
+                    "Loaded {} entries in {} ms!",
+                    loaded,
+                    start.elapsed().as_micros() as f32 / 1000.0
+                );
+
+                let mut app_dirs = discovery::application_dirs();
+                app_dirs.extend(config.app_dirs.iter().cloned());
+
+                let mut reloader = Reloader::new(&config);
+
+                let watched = watch::run(&app_dirs, &config.appimage_dirs, |events| {
+                    let changes = match (&config.command, config.prefer) {
+                        (Command::Menu, None) => reloader.reload(&config, &catalog, &events),
+                        // Like loading, these depend on all entries.
+                        _ => None,
+                    };
+
+                    match changes {
+                        Some(changes) => {
+                            let entries = menu_entries(changes.entries, &categories, &config);
+                            menu.replace_entries(&changes.ids, entries);
                         },
-                        Err(e) => {
-                            match e {
-                                DesktopEntryErr::NotApplication
-                                | DesktopEntryErr::Hidden
-                                | DesktopEntryErr::OnlyShowIn
-                                | DesktopEntryErr::NotShowIn => None,
-                                e => {
-                                    println!("Failed to parse desktop file: {:?}: {:?}", file, e);
-                                    None
-                                },
-                            }
+                        None => {
+                            reloader = Reloader::new(&config);
+                            menu.set_entries(load_entries(&config, &categories, &catalog));
                         },
                     }
-                })
-                .collect();
+                });
 
-            for category in &mut categories {
-                category.add_entries(&entries);
-            }
-
-            categories.retain(|c| !c.entries.is_empty());
-            let _menu = Menu::new(basalt.clone(), categories, entries);
+                if let Err(e) = watched {
+                    println!(
+                        "Warning: Failed to watch the application directories ({})",
+                        e
+                    );
+                }
+            });
 
-            println!(
-                "Launched in {} ms!",
-                start.elapsed().as_micros() as f32 / 1000.0
-            );
             basalt.wait_for_exit().unwrap();
         }),
     );
 }
+
+/// The built-in categories, one per main category with its additional categories as
+/// sub-categories. Entries without a recognized main category are listed under Other.
+fn menu_categories() -> Vec<Category> {
+    let mut education = Category::main_category("education", "Education", "applications-science");
+    // Science shares its additional categories with Education.
+    education.rule = Rule::Or(vec![
+        education.rule,
+        Rule::MainCategory(String::from("science")),
+    ]);
+
+    vec![
+        Category::main_category("utility", "Accessories", "applications-utilities"),
+        Category::main_category("development", "Development", "applications-development"),
+        education,
+        Category::main_category("game", "Games", "applications-games"),
+        Category::main_category("graphics", "Graphics", "applications-graphics"),
+        Category::main_category("audiovideo", "Multimedia", "applications-multimedia"),
+        Category::main_category("network", "Network", "applications-internet"),
+        Category::main_category("office", "Office", "applications-office"),
+        Category::unallocated("other", "Other", "applications-other"),
+        Category::main_category("settings", "Settings", "applications-accessories"),
+        Category::main_category("system", "System", "applications-system"),
+    ]
+}
+
+/// Every entry the menu shows, once all of them are loaded.
+fn load_entries(
+    config: &Config,
+    categories: &[Category],
+    catalog: &Catalog,
+) -> Vec<Arc<DesktopEntry>> {
+    let mut entries = Vec::new();
+    loader::load(config, catalog, |batch| entries.extend(batch));
+
+    if let Some(prefer) = config.prefer {
+        entries = prefer_origin(entries, prefer);
+    }
+
+    match &config.command {
+        Command::Open(files) => open_with_entries(entries, files, &config.desktops),
+        _ => menu_entries(entries, categories, config),
+    }
+}
+
+/// The entries that belong in one of the menu's categories. Entries with `NoDisplay=true` are
+/// only kept for search if enabled.
+fn menu_entries(
+    entries: Vec<DesktopEntry>,
+    categories: &[Category],
+    config: &Config,
+) -> Vec<Arc<DesktopEntry>> {
+    entries
+        .into_iter()
+        .filter(|entry| {
+            if entry.no_display {
+                config.search_no_display
+            } else {
+                categories.iter().any(|c| c.rule.matches(entry))
+            }
+        })
+        .map(Arc::new)
+        .collect()
+}
+
+/// Drop the entries of applications that are also installed from the preferred origin, e.g. the
+/// native Firefox when Flatpak is preferred.
+fn prefer_origin(entries: Vec<DesktopEntry>, prefer: Origin) -> Vec<DesktopEntry> {
+    let preferred: HashSet<String> = entries
+        .iter()
+        .filter(|entry| entry.origin == prefer)
+        .map(|entry| entry.duplicate_key())
+        .collect();
+
+    entries
+        .into_iter()
+        .filter(|entry| entry.origin == prefer || !preferred.contains(&entry.duplicate_key()))
+        .collect()
+}
+
+/// The applications able to open every file. The `mimeapps.list` default comes first, followed by
+/// the applications declaring the most specific types.
+fn open_with_entries(
+    entries: Vec<DesktopEntry>,
+    files: &[PathBuf],
+    desktops: &[String],
+) -> Vec<Arc<DesktopEntry>> {
+    let database = MimeDatabase::load();
+    let types: Vec<Vec<String>> = files
+        .iter()
+        .map(|file| database.ancestors(&database.type_of(file)))
+        .collect();
+    let mime_apps = MimeApps::load(desktops);
+    let defaults: Vec<&String> = types[0]
+        .iter()
+        .flat_map(|mime| mime_apps.defaults(mime))
+        .collect();
+
+    let mut ranked: Vec<_> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            if entry.kind != DesktopEntryKind::Application
+                || !entry
+                    .exec
+                    .as_deref()
+                    .map(exec::accepts_files)
+                    .unwrap_or(false)
+            {
+                return None;
+            }
+
+            let declared: Vec<String> = entry
+                .mime_types
+                .iter()
+                .map(|mime| database.unalias(mime))
+                .collect();
+            let mut distance = 0;
+
+            for ancestors in types.iter() {
+                distance += ancestors.iter().position(|mime| {
+                    mime_apps.associations(mime).contains(&entry.id)
+                        || (declared.contains(mime)
+                            && !mime_apps.is_removed(mime, &entry.id, &entry.source))
+                })?;
+            }
+
+            let default = defaults
+                .iter()
+                .position(|id| **id == entry.id)
+                .unwrap_or(usize::MAX);
+            Some((default, distance, entry))
+        })
+        .collect();
+
+    ranked
+        .sort_by_key(|(default, distance, entry)| (*default, *distance, entry.name.to_lowercase()));
+    ranked
+        .into_iter()
+        .map(|(_, _, entry)| Arc::new(entry))
+        .collect()
+}
@@ -1,40 +1,163 @@
 #![allow(dead_code)]
 
-use std::process::Command;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use basalt::ilmenite::ImtTextWrap;
 use basalt::input::{Character, InputHook, InputHookData, InputHookRes, MouseButton};
 use basalt::interface::bin::{Bin, BinPosition, BinStyle, Color};
-use basalt::interface::hook::{BinHook, BinHookFn};
+use basalt::interface::hook::{BinHook, BinHookData, BinHookFn};
 use basalt::Basalt;
 
-use crate::category::Category;
-use crate::desktop::DesktopEntry;
+use crate::category::{self, Category};
+use crate::desktop::{DesktopAction, DesktopEntry, Origin};
+use crate::{exec, xdg};
+
+const BADGE_WIDTH: f32 = 38.0;
+/// How far sub-categories are indented in the navigation, per level.
+const NAV_INDENT: f32 = 8.0;
+/// The navigation rows that fit above the search bar, the others are scrolled to.
+const NAV_ROWS: usize = 15;
+/// The `hicolor` icon sizes tried for category icons, the closest to the tooltip's first.
+const ICON_SIZES: &[&str] = &["16x16", "22x22", "24x24", "32x32", "48x48"];
+
+/// What the right side of the menu shows, so it can be shown again when entries are added.
+#[derive(Clone, Debug, PartialEq)]
+enum View {
+    Nothing,
+    Category(String),
+    Search(String),
+    All,
+    Actions,
+}
 
 pub struct Menu {
     basalt: Arc<Basalt>,
-    categories: Vec<Arc<MenuCategory>>,
-    entries: Vec<Arc<DesktopEntry>>,
+    /// Every category, each is shown once it has entries.
+    all_categories: Mutex<Vec<Category>>,
+    /// The shown categories, each followed by its sub-categories.
+    categories: Mutex<Vec<Arc<MenuCategory>>>,
+    /// Idens of the categories whose sub-categories are shown in the navigation.
+    expanded: Mutex<HashSet<String>>,
+    /// The first navigation row shown.
+    nav_scroll: Mutex<usize>,
+    entries: Mutex<Vec<Arc<DesktopEntry>>>,
     container: Arc<Bin>,
     right: Arc<Bin>,
     search: Arc<Bin>,
+    /// The comment & icon of the category under the mouse, next to its navigation bin.
+    tooltip: Arc<Bin>,
+    tooltip_icon: Arc<Bin>,
     search_entries: Mutex<Vec<Arc<MenuEntry>>>,
+    view: Mutex<View>,
+    /// Files passed to the launched application.
+    files: Vec<PathBuf>,
+    /// Duplicate keys of the applications installed from more than one origin.
+    duplicates: Mutex<HashSet<String>>,
 }
 
 pub struct MenuCategory {
+    /// The category without its sub-categories, they have their own `MenuCategory`.
     category: Category,
+    /// Idens of the parent categories, outermost first.
+    ancestors: Vec<String>,
+    has_children: bool,
+    /// The file of the category's icon, if it can be shown.
+    icon: Option<PathBuf>,
     nav_bin: Arc<Bin>,
     entries: Vec<Arc<MenuEntry>>,
 }
 
 pub struct MenuEntry {
     entry_bin: Arc<Bin>,
+    expander_bin: Option<Arc<Bin>>,
+    badge: Option<&'static str>,
+    badge_bin: Option<Arc<Bin>>,
     entry: Arc<DesktopEntry>,
+    action: Option<usize>,
 }
 
 impl MenuEntry {
-    fn add_hooks(&self, basalt: Arc<Basalt>) {
+    fn new(
+        basalt: &Arc<Basalt>,
+        entry_bin: Arc<Bin>,
+        entry: Arc<DesktopEntry>,
+        action: Option<usize>,
+        badge: Option<&'static str>,
+    ) -> Self {
+        let expander_bin = if action.is_none() && !entry.actions.is_empty() {
+            let expander_bin = basalt.interface_ref().new_bin();
+            entry_bin.add_child(expander_bin.clone());
+
+            expander_bin.style_update(BinStyle {
+                position: Some(BinPosition::Parent),
+                pos_from_t: Some(0.0),
+                pos_from_b: Some(0.0),
+                pos_from_r: Some(0.0),
+                width: Some(16.0),
+                pad_t: Some(6.0),
+                pad_l: Some(5.0),
+                back_color: Some(Color::srgb_hex("ffffff10")),
+                border_radius_tr: Some(2.0),
+                border_radius_br: Some(2.0),
+                text: String::from(">"),
+                text_height: Some(12.5),
+                text_color: Some(Color::srgb_hex("f8f8f8ff")),
+                ..BinStyle::default()
+            });
+
+            Some(expander_bin)
+        } else {
+            None
+        };
+
+        let badge_bin = badge.map(|badge| {
+            let badge_bin = basalt.interface_ref().new_bin();
+            entry_bin.add_child(badge_bin.clone());
+
+            badge_bin.style_update(BinStyle {
+                position: Some(BinPosition::Parent),
+                pos_from_t: Some(0.0),
+                pos_from_b: Some(0.0),
+                pos_from_r: Some(if expander_bin.is_some() { 16.0 } else { 0.0 }),
+                width: Some(BADGE_WIDTH),
+                pad_t: Some(8.0),
+                pad_l: Some(4.0),
+                text: String::from(badge),
+                text_height: Some(9.5),
+                text_color: Some(Color::srgb_hex("f8f8f880")),
+                ..BinStyle::default()
+            });
+
+            badge_bin
+        });
+
+        MenuEntry {
+            entry_bin,
+            expander_bin,
+            badge,
+            badge_bin,
+            entry,
+            action,
+        }
+    }
+
+    /// Right padding of the entry text, keeping it clear of the badge.
+    fn pad_r(&self) -> f32 {
+        if self.badge_bin.is_some() {
+            8.0 + BADGE_WIDTH
+        } else {
+            8.0
+        }
+    }
+
+    fn desktop_action(&self) -> Option<&DesktopAction> {
+        self.action.and_then(|i| self.entry.actions.get(i))
+    }
+
+    fn add_hooks(&self, menu: &Arc<Menu>) {
         self.entry_bin.add_hook_raw(
             BinHook::MouseEnter,
             Arc::new(move |bin, _| {
@@ -56,55 +179,66 @@ impl MenuEntry {
         );
 
         let entry = self.entry.clone();
+        let action = self.action;
+        let menu_cp = menu.clone();
 
         self.entry_bin.on_mouse_press(
             MouseButton::Left,
             Arc::new(move |_, _| {
-                let exec = entry
-                    .exec
-                    .replace("%f", "")
-                    .replace("%F", "")
-                    .replace("%u", "")
-                    .replace("%U", "")
-                    .replace(
-                        "%i",
-                        entry
-                            .icon
-                            .clone()
-                            .as_ref()
-                            .map(|v| v.as_str())
-                            .unwrap_or(""),
-                    )
-                    .replace("%c", entry.name.as_str())
-                    .replace("%k", "");
-                Command::new("sh").arg("-c").arg(exec).spawn().unwrap();
-                basalt.exit();
+                if entry.try_exec_failed {
+                    return;
+                }
+
+                let action = action.and_then(|i| entry.actions.get(i));
+
+                if let Err(e) = exec::launch(&entry, action, &menu_cp.files) {
+                    println!("Failed to launch {}: {}", entry.id, e);
+                    return;
+                }
+
+                menu_cp.basalt.exit();
             }),
         );
+
+        if let Some(expander_bin) = self.expander_bin.as_ref() {
+            let menu = menu.clone();
+            let entry = self.entry.clone();
+            let open_actions: BinHookFn = Arc::new(move |_, _| menu.display_actions(&entry));
+
+            self.entry_bin
+                .on_mouse_press(MouseButton::Right, open_actions.clone());
+            expander_bin.on_mouse_press(MouseButton::Left, open_actions);
+        }
     }
 }
 
 impl Menu {
-    pub fn new(
-        basalt: Arc<Basalt>,
-        categories: Vec<Category>,
-        entries: Vec<Arc<DesktopEntry>>,
-    ) -> Arc<Self> {
-        let total_bins: usize = categories.iter().map(|c| c.entries.len() + 1).sum();
-        let mut bins = basalt.interface_ref().new_bins(total_bins + 3);
-
-        let mut menu = Menu {
+    /// An empty menu. Entries are added with [`Menu::add_entries`] as they are loaded.
+    pub fn new(basalt: Arc<Basalt>, categories: Vec<Category>, files: Vec<PathBuf>) -> Arc<Self> {
+        let mut bins = basalt.interface_ref().new_bins(5);
+
+        let menu = Menu {
             basalt,
-            categories: Vec::with_capacity(categories.len()),
-            entries,
+            all_categories: Mutex::new(categories),
+            categories: Mutex::new(Vec::new()),
+            expanded: Mutex::new(HashSet::new()),
+            nav_scroll: Mutex::new(0),
+            entries: Mutex::new(Vec::new()),
             container: bins.pop().unwrap(),
             right: bins.pop().unwrap(),
             search: bins.pop().unwrap(),
+            tooltip: bins.pop().unwrap(),
+            tooltip_icon: bins.pop().unwrap(),
             search_entries: Mutex::new(Vec::new()),
+            view: Mutex::new(View::Nothing),
+            files,
+            duplicates: Mutex::new(HashSet::new()),
         };
 
         menu.container.add_child(menu.right.clone());
         menu.container.add_child(menu.search.clone());
+        menu.container.add_child(menu.tooltip.clone());
+        menu.tooltip.add_child(menu.tooltip_icon.clone());
 
         menu.container.style_update(BinStyle {
             position: Some(BinPosition::Window),
@@ -150,129 +284,542 @@ impl Menu {
             ..BinStyle::default()
         });
 
-        for (ci, category) in categories.into_iter().enumerate() {
-            let entries_len = category.entries.len();
-            let mut menu_category = MenuCategory {
-                category,
-                nav_bin: bins.pop().unwrap(),
-                entries: Vec::with_capacity(entries_len),
-            };
-            menu.container.add_child(menu_category.nav_bin.clone());
+        menu.tooltip.style_update(BinStyle {
+            hidden: Some(true),
+            position: Some(BinPosition::Parent),
+            add_z_index: Some(10),
+            pos_from_l: Some(103.0),
+            pos_from_r: Some(3.0),
+            height: Some(24.0),
+            back_color: Some(Color::srgb_hex("3a3a3cff")),
+            border_radius_tl: Some(2.0),
+            border_radius_tr: Some(2.0),
+            border_radius_bl: Some(2.0),
+            border_radius_br: Some(2.0),
+            pad_t: Some(6.0),
+            pad_r: Some(6.0),
+            text_height: Some(12.5),
+            text_color: Some(Color::srgb_hex("f8f8f8c0")),
+            text_wrap: Some(ImtTextWrap::NoneDotted),
+            ..BinStyle::default()
+        });
 
-            menu_category.nav_bin.style_update(BinStyle {
-                position: Some(BinPosition::Parent),
-                pos_from_t: Some((ci as f32 * 25.0) + 6.0),
-                pos_from_l: Some(3.0),
-                pad_l: Some(5.0),
-                width: Some(97.0),
-                height: Some(24.0),
-                pad_t: Some(5.0),
-                text: menu_category.category.name.clone(),
-                text_height: Some(12.5),
-                text_color: Some(Color::srgb_hex("f8f8f8ff")),
-                overflow_y: Some(true),
-                ..BinStyle::default()
-            });
+        menu.tooltip_icon.style_update(BinStyle {
+            position: Some(BinPosition::Parent),
+            pos_from_t: Some(4.0),
+            pos_from_l: Some(4.0),
+            width: Some(16.0),
+            height: Some(16.0),
+            ..BinStyle::default()
+        });
 
-            let mut x = 3.0;
-            let mut y = 3.0;
-
-            for entry in menu_category.category.entries.iter() {
-                let menu_entry = MenuEntry {
-                    entry_bin: bins.pop().unwrap(),
-                    entry: entry.clone(),
-                };
-                menu.right.add_child(menu_entry.entry_bin.clone());
-
-                menu_entry.entry_bin.style_update(BinStyle {
-                    hidden: Some(true),
-                    position: Some(BinPosition::Parent),
-                    pos_from_t: Some(y),
-                    pos_from_l: Some(x),
-                    width: Some(150.0),
-                    height: Some(24.0),
-                    back_color: Some(Color::srgb_hex("ffffff1a")),
-                    pad_t: Some(6.0),
-                    pad_l: Some(6.0),
-                    pad_r: Some(8.0),
-                    border_radius_tl: Some(2.0),
-                    border_radius_tr: Some(2.0),
-                    border_radius_bl: Some(2.0),
-                    border_radius_br: Some(2.0),
-                    text: entry.name.clone(),
-                    text_height: Some(12.5),
-                    text_color: Some(Color::srgb_hex("f8f8f8ff")),
-                    text_wrap: Some(ImtTextWrap::None),
-                    ..BinStyle::default()
-                });
+        let menu = Arc::new(menu);
+        menu.add_hooks();
+        menu
+    }
+
+    /// Add entries to the menu & sort them into the categories. Whatever is shown is updated to
+    /// include them.
+    pub fn add_entries(self: &Arc<Self>, entries: Vec<Arc<DesktopEntry>>) {
+        let mut all_entries = self.entries.lock().unwrap().clone();
+        all_entries.extend(entries.iter().cloned());
+        self.update_entries(all_entries, Vec::new(), entries);
+    }
+
+    /// Replace all entries, e.g. after desktop files changed. Entries whose files didn't change
+    /// are kept, whatever is shown is updated.
+    pub fn set_entries(self: &Arc<Self>, entries: Vec<Arc<DesktopEntry>>) {
+        let fingerprint = |entry: &Arc<DesktopEntry>| {
+            (
+                entry.id.clone(),
+                entry.source.clone(),
+                entry.modified,
+                entry.try_exec_failed,
+            )
+        };
 
-                y += 25.0;
+        let mut current: HashMap<_, Arc<DesktopEntry>> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| (fingerprint(entry), entry.clone()))
+            .collect();
 
-                if y >= 420.0 {
-                    x += 151.0;
-                    y = 3.0;
+        let mut added = Vec::new();
+
+        // The order of `entries` is kept, it may be a ranking.
+        let all_entries = entries
+            .into_iter()
+            .map(|entry| {
+                match current.remove(&fingerprint(&entry)) {
+                    Some(unchanged) => unchanged,
+                    None => {
+                        added.push(entry.clone());
+                        entry
+                    },
                 }
+            })
+            .collect();
+
+        let removed = current.into_values().collect();
+        self.update_entries(all_entries, removed, added);
+    }
+
+    /// Replace the entries of the desktop-file IDs `ids` with `entries`, e.g. after their files
+    /// changed. IDs without an entry are removed.
+    pub fn replace_entries(self: &Arc<Self>, ids: &[String], entries: Vec<Arc<DesktopEntry>>) {
+        let ids: HashSet<&str> = ids.iter().map(|id| id.as_str()).collect();
+        let (removed, mut all_entries): (Vec<_>, Vec<_>) = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .cloned()
+            .partition(|entry| ids.contains(entry.id.as_str()));
+
+        all_entries.extend(entries.iter().cloned());
+        self.update_entries(all_entries, removed, entries);
+    }
+
+    /// Set all entries, updating the categories with the ones removed & added.
+    fn update_entries(
+        self: &Arc<Self>,
+        all_entries: Vec<Arc<DesktopEntry>>,
+        removed: Vec<Arc<DesktopEntry>>,
+        added: Vec<Arc<DesktopEntry>>,
+    ) {
+        if removed.is_empty() && added.is_empty() {
+            return;
+        }
 
-                menu_category.entries.push(Arc::new(menu_entry));
+        let duplicates = duplicate_keys(&all_entries);
+        *self.entries.lock().unwrap() = all_entries;
+
+        // Entries of these applications gain or lose their badge.
+        let badges_changed: HashSet<String> = {
+            let mut current = self.duplicates.lock().unwrap();
+            let changed = current.symmetric_difference(&duplicates).cloned().collect();
+            *current = duplicates;
+            changed
+        };
+
+        let nav_changed = {
+            let mut all_categories = self.all_categories.lock().unwrap();
+
+            for category in all_categories.iter_mut() {
+                category.remove_entries(&removed);
             }
 
-            menu.categories.push(Arc::new(menu_category));
+            category::distribute(&mut all_categories, &added);
+
+            let mut categories = self.categories.lock().unwrap();
+            let mut previous: HashMap<String, Arc<MenuCategory>> = categories
+                .iter()
+                .map(|menu_cat| (menu_cat.category.iden.clone(), menu_cat.clone()))
+                .collect();
+            let mut updated = Vec::with_capacity(categories.len());
+
+            self.update_categories(
+                &mut all_categories,
+                &[],
+                &mut previous,
+                &badges_changed,
+                &mut updated,
+            );
+
+            let nav_changed = updated.len() != categories.len()
+                || updated.iter().zip(categories.iter()).any(|(a, b)| {
+                    a.category.iden != b.category.iden || a.has_children != b.has_children
+                });
+
+            // The bins of categories that are no longer shown are removed as they're dropped.
+            *categories = updated;
+            nav_changed
+        };
+
+        if nav_changed {
+            self.layout_nav();
         }
 
-        let menu = Arc::new(menu);
-        menu.add_hooks();
-        menu
+        let view = self.view.lock().unwrap().clone();
+
+        match view {
+            View::Category(iden) => self.display_category(&iden),
+            View::Search(text) => self.display_search(text),
+            View::All => self.display_all(),
+            View::Nothing | View::Actions => (),
+        }
     }
 
-    fn add_hooks(self: &Arc<Self>) {
-        let menu = self.clone();
+    /// The shown categories of a tree, each followed by its sub-categories. Categories whose
+    /// entries didn't change are taken from `previous` as they are, the others keep the bins of
+    /// the entries they still have.
+    fn update_categories(
+        self: &Arc<Self>,
+        categories: &mut [Category],
+        ancestors: &[String],
+        previous: &mut HashMap<String, Arc<MenuCategory>>,
+        badges_changed: &HashSet<String>,
+        updated: &mut Vec<Arc<MenuCategory>>,
+    ) {
+        for category in categories.iter_mut() {
+            if category.entries.is_empty() {
+                continue;
+            }
 
-        let nav_enter_func: BinHookFn = Arc::new(move |bin: Arc<Bin>, _| {
-            menu.search_entries.lock().unwrap().clear();
+            let has_children = category.children.iter().any(|c| !c.entries.is_empty());
+            let children = std::mem::take(&mut category.children);
 
-            for menu_cat in menu.categories.iter() {
-                if bin.id() == menu_cat.nav_bin.id() {
-                    menu_cat.entries.iter().for_each(|e| {
-                        e.entry_bin.hidden(Some(false));
-                    });
+            let menu_cat = match previous.remove(&category.iden) {
+                Some(menu_cat)
+                    if menu_cat.has_children == has_children
+                        && same_entries(&menu_cat.entries, &category.entries)
+                        && (badges_changed.is_empty()
+                            || !category
+                                .entries
+                                .iter()
+                                .any(|e| badges_changed.contains(&e.duplicate_key()))) =>
+                {
+                    menu_cat
+                },
+                menu_cat => {
+                    Arc::new(self.menu_category(
+                        category.clone(),
+                        ancestors.to_vec(),
+                        has_children,
+                        menu_cat,
+                    ))
+                },
+            };
+
+            category.children = children;
+            updated.push(menu_cat);
+
+            let mut child_ancestors = ancestors.to_vec();
+            child_ancestors.push(category.iden.clone());
+
+            self.update_categories(
+                &mut category.children,
+                &child_ancestors,
+                previous,
+                badges_changed,
+                updated,
+            );
+        }
+    }
 
-                    menu_cat.nav_bin.style_update(BinStyle {
+    /// The bins of a category without its sub-categories. The navigation bin & the entry bins
+    /// of `previous` are reused, entry bins are only created for new entries. The navigation is
+    /// positioned by [`Menu::layout_nav`].
+    fn menu_category(
+        self: &Arc<Self>,
+        category: Category,
+        ancestors: Vec<String>,
+        has_children: bool,
+        previous: Option<Arc<MenuCategory>>,
+    ) -> MenuCategory {
+        let mut reused: HashMap<*const DesktopEntry, Arc<MenuEntry>> = previous
+            .as_ref()
+            .map(|menu_cat| {
+                menu_cat
+                    .entries
+                    .iter()
+                    .map(|e| (Arc::as_ptr(&e.entry), e.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let (nav_bin, icon) = match previous.as_ref() {
+            Some(menu_cat) => (menu_cat.nav_bin.clone(), menu_cat.icon.clone()),
+            None => (self.nav_bin(&category), icon_path(&category.icon)),
+        };
+
+        let new_entries = category
+            .entries
+            .iter()
+            .filter(|entry| !reused.contains_key(&Arc::as_ptr(entry)))
+            .count();
+        let mut bins = self.basalt.interface_ref().new_bins(new_entries);
+        let mut entries = Vec::with_capacity(category.entries.len());
+        let mut x = 3.0;
+        let mut y = 3.0;
+
+        for entry in category.entries.iter() {
+            let badge = self.badge(entry);
+
+            let menu_entry = match reused.remove(&Arc::as_ptr(entry)) {
+                Some(menu_entry) if menu_entry.badge == badge => {
+                    let style = menu_entry.entry_bin.style_copy();
+
+                    if style.pos_from_t != Some(y) || style.pos_from_l != Some(x) {
+                        menu_entry.entry_bin.style_update(BinStyle {
+                            pos_from_t: Some(y),
+                            pos_from_l: Some(x),
+                            ..style
+                        });
+                    }
+
+                    menu_entry
+                },
+                _ => {
+                    let entry_bin = match bins.pop() {
+                        Some(some) => some,
+                        None => self.basalt.interface_ref().new_bin(),
+                    };
+
+                    let menu_entry =
+                        MenuEntry::new(&self.basalt, entry_bin, entry.clone(), None, badge);
+                    self.right.add_child(menu_entry.entry_bin.clone());
+
+                    menu_entry.entry_bin.style_update(BinStyle {
+                        hidden: Some(true),
+                        position: Some(BinPosition::Parent),
+                        pos_from_t: Some(y),
+                        pos_from_l: Some(x),
+                        width: Some(150.0),
+                        height: Some(24.0),
+                        back_color: Some(Color::srgb_hex("ffffff1a")),
+                        pad_t: Some(6.0),
+                        pad_l: Some(6.0),
+                        pad_r: Some(menu_entry.pad_r()),
                         border_radius_tl: Some(2.0),
                         border_radius_tr: Some(2.0),
                         border_radius_bl: Some(2.0),
                         border_radius_br: Some(2.0),
-                        back_color: Some(Color::srgb_hex("ffffff20")),
-                        ..menu_cat.nav_bin.style_copy()
-                    });
-                } else {
-                    menu_cat.entries.iter().for_each(|e| {
-                        e.entry_bin.hidden(Some(true));
+                        text: entry.name.clone(),
+                        text_height: Some(12.5),
+                        text_color: Some(entry_text_color(entry)),
+                        text_wrap: Some(ImtTextWrap::None),
+                        ..BinStyle::default()
                     });
 
-                    menu_cat.nav_bin.style_update(BinStyle {
-                        border_radius_tl: None,
-                        border_radius_tr: None,
-                        border_radius_bl: None,
-                        border_radius_br: None,
-                        back_color: None,
-                        ..menu_cat.nav_bin.style_copy()
-                    });
-                }
+                    menu_entry.add_hooks(self);
+                    Arc::new(menu_entry)
+                },
+            };
+
+            y += 25.0;
+
+            if y >= 420.0 {
+                x += 151.0;
+                y = 3.0;
             }
+
+            entries.push(menu_entry);
+        }
+
+        MenuCategory {
+            category,
+            ancestors,
+            has_children,
+            icon,
+            nav_bin,
+            entries,
+        }
+    }
+
+    /// The navigation bin of a category, hidden until [`Menu::layout_nav`] positions it.
+    fn nav_bin(self: &Arc<Self>, category: &Category) -> Arc<Bin> {
+        let nav_bin = self.basalt.interface_ref().new_bin();
+        self.container.add_child(nav_bin.clone());
+
+        nav_bin.style_update(BinStyle {
+            hidden: Some(true),
+            position: Some(BinPosition::Parent),
+            pos_from_l: Some(3.0),
+            pad_l: Some(5.0),
+            height: Some(24.0),
+            pad_t: Some(5.0),
+            text: category.name.clone(),
+            text_height: Some(12.5),
+            text_color: Some(Color::srgb_hex("f8f8f8ff")),
+            overflow_y: Some(true),
+            ..BinStyle::default()
+        });
+
+        let menu = self.clone();
+        let iden = category.iden.clone();
+
+        nav_bin.add_hook_raw(
+            BinHook::MouseEnter,
+            Arc::new(move |_, _| {
+                menu.display_category(&iden);
+                menu.show_tooltip(&iden);
+            }),
+        );
+
+        let menu = self.clone();
+
+        nav_bin.add_hook_raw(
+            BinHook::MouseLeave,
+            Arc::new(move |_, _| menu.tooltip.hidden(Some(true))),
+        );
+
+        let menu = self.clone();
+
+        nav_bin.add_hook_raw(
+            BinHook::MouseScroll,
+            Arc::new(move |_, data| {
+                if let BinHookData::MouseScroll {
+                    scroll_amt,
+                } = data
+                {
+                    menu.scroll_nav(*scroll_amt);
+                }
+            }),
+        );
+
+        // Sub-categories may only get entries later on.
+        let menu = self.clone();
+        let iden = category.iden.clone();
+
+        nav_bin.on_mouse_press(
+            MouseButton::Left,
+            Arc::new(move |_, _| menu.toggle_category(&iden)),
+        );
+
+        nav_bin
+    }
+
+    /// Show the comment & icon of a category next to its navigation bin, if it has either.
+    fn show_tooltip(&self, iden: &str) {
+        let categories = self.categories.lock().unwrap();
+
+        let menu_cat = match categories
+            .iter()
+            .find(|menu_cat| menu_cat.category.iden == iden)
+        {
+            Some(some) => some,
+            None => return,
+        };
+
+        if menu_cat.category.comment.is_none() && menu_cat.icon.is_none() {
+            self.tooltip.hidden(Some(true));
+            return;
+        }
+
+        self.tooltip_icon.style_update(BinStyle {
+            hidden: Some(menu_cat.icon.is_none()),
+            back_image: menu_cat
+                .icon
+                .as_ref()
+                .map(|icon| icon.to_string_lossy().into_owned()),
+            ..self.tooltip_icon.style_copy()
         });
 
-        for menu_cat in self.categories.iter() {
+        self.tooltip.style_update(BinStyle {
+            hidden: Some(false),
+            pos_from_t: menu_cat.nav_bin.style_copy().pos_from_t,
+            pad_l: Some(if menu_cat.icon.is_some() { 24.0 } else { 6.0 }),
+            text: menu_cat.category.comment.clone().unwrap_or_default(),
+            ..self.tooltip.style_copy()
+        });
+    }
+
+    /// Show or hide the sub-categories of a category in the navigation.
+    fn toggle_category(&self, iden: &str) {
+        if !self
+            .categories
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|menu_cat| menu_cat.category.iden == iden && menu_cat.has_children)
+        {
+            return;
+        }
+
+        {
+            let mut expanded = self.expanded.lock().unwrap();
+
+            if !expanded.remove(iden) {
+                expanded.insert(iden.to_owned());
+            }
+        }
+
+        self.layout_nav();
+    }
+
+    /// Scroll the navigation by a row towards the direction of `amount`.
+    fn scroll_nav(&self, amount: f32) {
+        {
+            let mut nav_scroll = self.nav_scroll.lock().unwrap();
+
+            if amount > 0.0 {
+                *nav_scroll += 1;
+            } else if amount < 0.0 && *nav_scroll > 0 {
+                *nav_scroll -= 1;
+            } else {
+                return;
+            }
+        }
+
+        self.tooltip.hidden(Some(true));
+        self.layout_nav();
+    }
+
+    /// Position the navigation bins of the categories that are shown, sub-categories only while
+    /// their parents are expanded. Only [`NAV_ROWS`] rows are shown at once, starting at the
+    /// scrolled to one.
+    fn layout_nav(&self) {
+        let expanded = self.expanded.lock().unwrap();
+        let categories = self.categories.lock().unwrap();
+        let (rows, collapsed): (Vec<_>, Vec<_>) = categories.iter().partition(|menu_cat| {
             menu_cat
-                .nav_bin
-                .add_hook_raw(BinHook::MouseEnter, nav_enter_func.clone());
+                .ancestors
+                .iter()
+                .all(|iden| expanded.contains(iden))
+        });
+
+        let mut nav_scroll = self.nav_scroll.lock().unwrap();
+        *nav_scroll = (*nav_scroll).min(rows.len().saturating_sub(NAV_ROWS));
+
+        for menu_cat in collapsed {
+            menu_cat.nav_bin.hidden(Some(true));
         }
 
-        for menu_cat in self.categories.iter() {
-            for menu_en in menu_cat.entries.iter() {
-                menu_en.add_hooks(self.basalt.clone());
+        for (row, menu_cat) in rows.into_iter().enumerate() {
+            if row < *nav_scroll || row >= *nav_scroll + NAV_ROWS {
+                menu_cat.nav_bin.hidden(Some(true));
+                continue;
             }
+
+            let indent = menu_cat.ancestors.len() as f32 * NAV_INDENT;
+            let text = if !menu_cat.has_children {
+                menu_cat.category.name.clone()
+            } else if expanded.contains(&menu_cat.category.iden) {
+                format!("{} -", menu_cat.category.name)
+            } else {
+                format!("{} +", menu_cat.category.name)
+            };
+
+            menu_cat.nav_bin.style_update(BinStyle {
+                hidden: Some(false),
+                pos_from_t: Some(((row - *nav_scroll) as f32 * 25.0) + 6.0),
+                pos_from_l: Some(3.0 + indent),
+                width: Some(97.0 - indent),
+                text,
+                ..menu_cat.nav_bin.style_copy()
+            });
         }
+    }
 
+    /// The origin of entries installed from Flatpak or Snap, or from more than one origin.
+    fn badge(&self, entry: &DesktopEntry) -> Option<&'static str> {
+        if entry.origin != Origin::Native
+            || self
+                .duplicates
+                .lock()
+                .unwrap()
+                .contains(&entry.duplicate_key())
+        {
+            Some(entry.origin.label())
+        } else {
+            None
+        }
+    }
+
+    fn add_hooks(self: &Arc<Self>) {
         let menu = self.clone();
 
         self.basalt.input_ref().add_hook(
@@ -308,42 +855,151 @@ impl Menu {
         );
     }
 
-    fn display_search(&self, text: String) {
-        let mut entries = self.entries.clone();
+    /// Switch to another view, hiding the entries of the category shown before.
+    fn set_view(&self, view: View) {
+        let previous = std::mem::replace(&mut *self.view.lock().unwrap(), view);
+        self.search_entries.lock().unwrap().clear();
 
-        entries.sort_by_key(|e| {
-            (strsim::jaro_winkler(e.name.as_str(), text.as_str()) * u64::max_value() as f64).floor()
-                as u64
-        });
+        let iden = match previous {
+            View::Category(iden) => iden,
+            _ => return,
+        };
 
-        for menu_cat in self.categories.iter() {
-            menu_cat.entries.iter().for_each(|e| {
-                e.entry_bin.hidden(Some(true));
-            });
+        for menu_cat in self.categories.lock().unwrap().iter() {
+            if menu_cat.category.iden == iden {
+                menu_cat.entries.iter().for_each(|e| {
+                    e.entry_bin.hidden(Some(true));
+                });
 
-            menu_cat.nav_bin.style_update(BinStyle {
-                border_size_b: None,
-                border_color_b: None,
-                ..menu_cat.nav_bin.style_copy()
-            });
+                menu_cat.nav_bin.style_update(BinStyle {
+                    border_radius_tl: None,
+                    border_radius_tr: None,
+                    border_radius_bl: None,
+                    border_radius_br: None,
+                    border_size_b: None,
+                    border_color_b: None,
+                    back_color: None,
+                    ..menu_cat.nav_bin.style_copy()
+                });
+            }
         }
+    }
+
+    fn display_category(&self, iden: &str) {
+        self.set_view(View::Category(iden.to_owned()));
+
+        for menu_cat in self.categories.lock().unwrap().iter() {
+            if menu_cat.category.iden == iden {
+                menu_cat.entries.iter().for_each(|e| {
+                    e.entry_bin.hidden(Some(false));
+                });
+
+                menu_cat.nav_bin.style_update(BinStyle {
+                    border_radius_tl: Some(2.0),
+                    border_radius_tr: Some(2.0),
+                    border_radius_bl: Some(2.0),
+                    border_radius_br: Some(2.0),
+                    back_color: Some(Color::srgb_hex("ffffff20")),
+                    ..menu_cat.nav_bin.style_copy()
+                });
+            }
+        }
+    }
+
+    fn display_search(self: &Arc<Self>, text: String) {
+        self.set_view(View::Search(text.clone()));
+        let text = text.to_lowercase();
+        let score = |name: &str| search_score(name.to_lowercase().as_str(), text.as_str());
+        let mut results = Vec::new();
+
+        // Matching a keyword or the summary ranks an entry below names starting with the query,
+        // but above names that are merely similar.
+        let term_score = |entry: &DesktopEntry| {
+            if entry
+                .search_terms()
+                .into_iter()
+                .any(|term| words_match(term.to_lowercase().as_str(), text.as_str()))
+            {
+                1.0
+            } else {
+                0.0
+            }
+        };
+
+        let entries = self.entries.lock().unwrap().clone();
 
+        for entry in entries.iter() {
+            results.push((
+                score(&entry.name)
+                    .max(score(&entry.untranslated_name))
+                    .max(term_score(entry)),
+                entry.clone(),
+                None,
+                entry.name.clone(),
+            ));
+
+            for (i, action) in entry.actions.iter().enumerate() {
+                results.push((
+                    score(&format!("{} {}", entry.name, action.name)).max(score(&format!(
+                        "{} {}",
+                        entry.untranslated_name, action.untranslated_name
+                    ))),
+                    entry.clone(),
+                    Some(i),
+                    format!("{} - {}", entry.name, action.name),
+                ));
+            }
+        }
+
+        results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+
+        self.display_entries(
+            results
+                .into_iter()
+                .map(|(_, entry, action, text)| (entry, action, text))
+                .collect(),
+        );
+    }
+
+    /// List every entry in order, e.g. the applications of the open with chooser.
+    pub fn display_all(self: &Arc<Self>) {
+        self.set_view(View::All);
+        let entries = self.entries.lock().unwrap().clone();
+
+        self.display_entries(
+            entries
+                .iter()
+                .map(|entry| (entry.clone(), None, entry.name.clone()))
+                .collect(),
+        );
+    }
+
+    fn display_actions(self: &Arc<Self>, entry: &Arc<DesktopEntry>) {
+        self.set_view(View::Actions);
+        let mut items = vec![(entry.clone(), None, entry.name.clone())];
+
+        for (i, action) in entry.actions.iter().enumerate() {
+            items.push((entry.clone(), Some(i), action.name.clone()));
+        }
+
+        self.display_entries(items);
+    }
+
+    fn display_entries(self: &Arc<Self>, items: Vec<(Arc<DesktopEntry>, Option<usize>, String)>) {
         let mut search_entries = self.search_entries.lock().unwrap();
         search_entries.clear();
         let mut x = 3.0;
         let mut y = 3.0;
         let mut bins = self.basalt.interface_ref().new_bins(34);
 
-        for entry in entries.into_iter().rev() {
+        for (entry, action, text) in items.into_iter() {
             if search_entries.len() >= 34 {
                 break;
             }
 
-            let menu_entry = MenuEntry {
-                entry_bin: bins.pop().unwrap(),
-                entry: entry.clone(),
-            };
-
+            let badge = self.badge(&entry);
+            let menu_entry =
+                MenuEntry::new(&self.basalt, bins.pop().unwrap(), entry, action, badge);
             self.right.add_child(menu_entry.entry_bin.clone());
 
             menu_entry.entry_bin.style_update(BinStyle {
@@ -355,14 +1011,14 @@ impl Menu {
                 back_color: Some(Color::srgb_hex("ffffff1a")),
                 pad_t: Some(6.0),
                 pad_l: Some(6.0),
-                pad_r: Some(8.0),
+                pad_r: Some(menu_entry.pad_r()),
                 border_radius_tl: Some(2.0),
                 border_radius_tr: Some(2.0),
                 border_radius_bl: Some(2.0),
                 border_radius_br: Some(2.0),
-                text: entry.name.clone(),
+                text,
                 text_height: Some(12.5),
-                text_color: Some(Color::srgb_hex("f8f8f8ff")),
+                text_color: Some(entry_text_color(&menu_entry.entry)),
                 text_wrap: Some(ImtTextWrap::None),
                 ..BinStyle::default()
             });
@@ -374,8 +1030,99 @@ impl Menu {
                 y = 3.0;
             }
 
-            menu_entry.add_hooks(self.basalt.clone());
+            menu_entry.add_hooks(self);
             search_entries.push(Arc::new(menu_entry));
         }
     }
 }
+
+/// The PNG file of an icon, either a path or a name looked up in the `hicolor` theme & `pixmaps`.
+/// Other formats can't be shown.
+fn icon_path(icon: &str) -> Option<PathBuf> {
+    let is_png = |path: &Path| path.extension().map(|e| e == "png") == Some(true) && path.is_file();
+
+    if Path::new(icon).is_absolute() {
+        return Some(PathBuf::from(icon)).filter(|path| is_png(path));
+    }
+
+    let file_name = format!("{}.png", icon);
+
+    for data_dir in xdg::data_dirs() {
+        let hicolor = data_dir.join("icons").join("hicolor");
+
+        for size in ICON_SIZES.iter() {
+            for context in ["categories", "apps", "places"].iter() {
+                let path = hicolor.join(size).join(context).join(&file_name);
+
+                if is_png(&path) {
+                    return Some(path);
+                }
+            }
+        }
+
+        let path = data_dir.join("pixmaps").join(&file_name);
+
+        if is_png(&path) {
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+/// Do the entry bins of a category show exactly these entries, in this order?
+fn same_entries(menu_entries: &[Arc<MenuEntry>], entries: &[Arc<DesktopEntry>]) -> bool {
+    menu_entries.len() == entries.len()
+        && menu_entries
+            .iter()
+            .zip(entries.iter())
+            .all(|(menu_entry, entry)| Arc::ptr_eq(&menu_entry.entry, entry))
+}
+
+/// The duplicate keys shared by entries of different origins.
+fn duplicate_keys(entries: &[Arc<DesktopEntry>]) -> HashSet<String> {
+    let mut origins: HashMap<String, Origin> = HashMap::new();
+    let mut duplicates = HashSet::new();
+
+    for entry in entries.iter() {
+        match origins.get(&entry.duplicate_key()) {
+            Some(origin) if *origin != entry.origin => {
+                duplicates.insert(entry.duplicate_key());
+            },
+            Some(_) => (),
+            None => {
+                origins.insert(entry.duplicate_key(), entry.origin);
+            },
+        }
+    }
+
+    duplicates
+}
+
+fn entry_text_color(entry: &DesktopEntry) -> Color {
+    if entry.try_exec_failed {
+        Color::srgb_hex("f8f8f860")
+    } else {
+        Color::srgb_hex("f8f8f8ff")
+    }
+}
+
+/// Similarity of a lowercase name to a lowercase query. Names where every word of the query is the
+/// start of a word in the name are always ranked above names where this isn't the case.
+fn search_score(name: &str, text: &str) -> f64 {
+    let similarity = strsim::jaro_winkler(name, text);
+
+    if words_match(name, text) {
+        similarity + 1.0
+    } else {
+        similarity
+    }
+}
+
+/// Is every word of the query the start of a word in `name`?
+fn words_match(name: &str, text: &str) -> bool {
+    !text.trim().is_empty()
+        && text
+            .split_whitespace()
+            .all(|t| name.split_whitespace().any(|n| n.starts_with(t)))
+}
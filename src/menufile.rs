@@ -0,0 +1,801 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, io};
+
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+use crate::category::{Category, Rule};
+use crate::directory::DirectoryFiles;
+use crate::{directory, xdg};
+
+/// The categories of a menu file as described by the freedesktop menu specification. Each
+/// top-level menu is a category, the entries of its submenus are part of it.
+///
+/// Legacy directories and `<Move>` aren't supported. Entries from every application directory
+/// and `<AppDir>` are available to all menus.
+#[derive(Clone, Debug, Default)]
+pub struct MenuFile {
+    pub categories: Vec<Category>,
+    /// The `<AppDir>`s of all menus.
+    pub app_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum MenuFileErr {
+    Xml(String),
+    NotAMenu,
+    IO(io::Error),
+}
+
+impl fmt::Display for MenuFileErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MenuFileErr::Xml(e) => write!(f, "{}", e),
+            MenuFileErr::NotAMenu => write!(f, "root element is not <Menu>"),
+            MenuFileErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MenuFileErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MenuFileErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// An element of a menu file with its text.
+#[derive(Clone, Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Clone, Debug)]
+enum LayoutItem {
+    Filename(String),
+    Menuname(String),
+    /// The `type` of a `<Merge>`: `menus`, `files` or `all`.
+    Merge(String),
+}
+
+/// A `<Menu>` including the files it merged. Options set more than once use the last value.
+#[derive(Clone, Debug, Default)]
+struct Node {
+    name: String,
+    directories: Vec<String>,
+    /// Later directories take precedence.
+    directory_dirs: Vec<PathBuf>,
+    app_dirs: Vec<PathBuf>,
+    /// `<Include>` & `<Exclude>` in order, `true` for an include.
+    rules: Vec<(bool, Rule)>,
+    only_unallocated: Option<bool>,
+    deleted: Option<bool>,
+    layout: Option<Vec<LayoutItem>>,
+    default_layout: Option<Vec<LayoutItem>>,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Append the contents of another menu, e.g. a merged file or a menu with the same name.
+    fn absorb(&mut self, other: Node) {
+        self.directories.extend(other.directories);
+        self.directory_dirs.extend(other.directory_dirs);
+        self.app_dirs.extend(other.app_dirs);
+        self.rules.extend(other.rules);
+        self.only_unallocated = other.only_unallocated.or(self.only_unallocated);
+        self.deleted = other.deleted.or(self.deleted);
+        self.layout = other.layout.or_else(|| self.layout.take());
+        self.default_layout = other.default_layout.or_else(|| self.default_layout.take());
+        self.children.extend(other.children);
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.deleted == Some(true)
+    }
+
+    /// The entries of this menu, `<Include>`s & `<Exclude>`s in order. An exclude only removes
+    /// what was included before it.
+    fn rule(&self) -> Rule {
+        let mut rule = Rule::Or(Vec::new());
+
+        for (include, next) in self.rules.iter().cloned() {
+            rule = if include {
+                Rule::Or(vec![rule, next])
+            } else {
+                Rule::And(vec![rule, Rule::Not(Box::new(next))])
+            };
+        }
+
+        rule
+    }
+
+    /// The entries of this menu and all its submenus.
+    fn tree_rule(&self) -> Rule {
+        let mut rules = vec![self.rule()];
+
+        for child in self.children.iter().filter(|c| !c.is_deleted()) {
+            rules.push(child.tree_rule());
+        }
+
+        Rule::Or(rules)
+    }
+
+    fn collect_app_dirs(&self, dirs: &mut Vec<PathBuf>) {
+        for dir in self.app_dirs.iter() {
+            if !dirs.contains(dir) {
+                dirs.push(dir.clone());
+            }
+        }
+
+        for child in self.children.iter().filter(|c| !c.is_deleted()) {
+            child.collect_app_dirs(dirs);
+        }
+    }
+}
+
+/// `${XDG_MENU_PREFIX}applications.menu` in the most important config dir that has one.
+pub fn menu_path() -> Option<PathBuf> {
+    let name = format!(
+        "{}applications.menu",
+        env::var("XDG_MENU_PREFIX").unwrap_or_default()
+    );
+
+    xdg::config_dirs()
+        .into_iter()
+        .map(|dir| dir.join("menus").join(&name))
+        .find(|path| path.is_file())
+}
+
+impl MenuFile {
+    /// The applications menu, `None` if there is none or it can't be read.
+    pub fn load() -> Option<Self> {
+        let path = menu_path()?;
+
+        match Self::open(&path) {
+            Ok(ok) => Some(ok),
+            Err(e) => {
+                println!("Warning: Failed to parse menu file: {:?} ({})", path, e);
+                None
+            },
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, MenuFileErr> {
+        let root = read_element(path)?;
+
+        if root.name != "Menu" {
+            return Err(MenuFileErr::NotAMenu);
+        }
+
+        let mut merging = vec![path.canonicalize().map_err(MenuFileErr::IO)?];
+        let mut node = Node::default();
+        read_menu(&root, path, &mut node, &mut merging);
+        consolidate(&mut node);
+
+        let mut app_dirs = Vec::new();
+        node.collect_app_dirs(&mut app_dirs);
+
+        Ok(MenuFile {
+            categories: categories(&node),
+            app_dirs,
+        })
+    }
+}
+
+fn read_element(path: &Path) -> Result<Element, MenuFileErr> {
+    let file = File::open(path).map_err(MenuFileErr::IO)?;
+    let parser = EventReader::new_with_config(
+        BufReader::new(file),
+        ParserConfig::new().trim_whitespace(true),
+    );
+
+    let mut stack: Vec<Element> = Vec::new();
+
+    for event in parser {
+        match event.map_err(|e| MenuFileErr::Xml(e.to_string()))? {
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                ..
+            } => {
+                stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|a| (a.name.local_name, a.value))
+                        .collect(),
+                    ..Element::default()
+                })
+            },
+            XmlEvent::Characters(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(&text);
+                }
+            },
+            XmlEvent::EndElement {
+                ..
+            } => {
+                let element = match stack.pop() {
+                    Some(some) => some,
+                    None => break,
+                };
+
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Err(MenuFileErr::NotAMenu)
+}
+
+/// Read the contents of a `<Menu>` element of `file` into `node`. `merging` are the files being
+/// merged, which aren't merged again.
+fn read_menu(element: &Element, file: &Path, node: &mut Node, merging: &mut Vec<PathBuf>) {
+    for child in element.children.iter() {
+        let text = child.text.trim();
+
+        match child.name.as_str() {
+            "Name" => node.name = text.to_owned(),
+            "Directory" => node.directories.push(text.to_owned()),
+            "DirectoryDir" => node.directory_dirs.push(resolve(file, text)),
+            "DefaultDirectoryDirs" => node.directory_dirs.extend(directory::default_dirs()),
+            "AppDir" => node.app_dirs.push(resolve(file, text)),
+            "Include" | "Exclude" => {
+                node.rules.push((
+                    child.name == "Include",
+                    Rule::Or(child.children.iter().filter_map(rule).collect()),
+                ))
+            },
+            "OnlyUnallocated" => node.only_unallocated = Some(true),
+            "NotOnlyUnallocated" => node.only_unallocated = Some(false),
+            "Deleted" => node.deleted = Some(true),
+            "NotDeleted" => node.deleted = Some(false),
+            "Layout" => node.layout = Some(layout(child)),
+            "DefaultLayout" => node.default_layout = Some(layout(child)),
+            "Menu" => {
+                let mut submenu = Node::default();
+                read_menu(child, file, &mut submenu, merging);
+                node.children.push(submenu);
+            },
+            "MergeFile" => {
+                let path = match child.attribute("type") {
+                    Some("parent") => parent_file(file),
+                    _ => Some(resolve(file, text)),
+                };
+
+                if let Some(path) = path {
+                    merge_file(&path, node, merging);
+                }
+            },
+            "MergeDir" => merge_dir(&resolve(file, text), node, merging),
+            "DefaultMergeDirs" => {
+                for dir in default_merge_dirs(file) {
+                    merge_dir(&dir, node, merging);
+                }
+            },
+            // `<DefaultAppDirs>` are always used.
+            _ => (),
+        }
+    }
+}
+
+/// Merge the contents of another menu file into `node`, its `<Name>` is ignored. Missing files
+/// are skipped silently.
+fn merge_file(path: &Path, node: &mut Node, merging: &mut Vec<PathBuf>) {
+    let canonical = match path.canonicalize() {
+        Ok(ok) => ok,
+        Err(_) => return,
+    };
+
+    // Merging a file that is being merged would never end.
+    if merging.contains(&canonical) {
+        return;
+    }
+
+    let root = match read_element(path) {
+        Ok(root) if root.name == "Menu" => root,
+        Ok(_) => {
+            println!(
+                "Warning: Failed to merge menu file: {:?} ({})",
+                path,
+                MenuFileErr::NotAMenu
+            );
+            return;
+        },
+        Err(e) => {
+            println!("Warning: Failed to merge menu file: {:?} ({})", path, e);
+            return;
+        },
+    };
+
+    merging.push(canonical);
+    let mut merged = Node::default();
+    read_menu(&root, path, &mut merged, merging);
+    merging.pop();
+
+    node.absorb(merged);
+}
+
+/// Merge every `.menu` file of a directory in the order of their names.
+fn merge_dir(dir: &Path, node: &mut Node, merging: &mut Vec<PathBuf>) {
+    let mut paths: Vec<PathBuf> = match dir.read_dir() {
+        Ok(entries) => {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|path| path.extension().map(|e| e == "menu") == Some(true))
+                .collect()
+        },
+        Err(_) => return,
+    };
+
+    paths.sort();
+
+    for path in paths {
+        merge_file(&path, node, merging);
+    }
+}
+
+/// A path of a menu file, relative ones are relative to the file's directory.
+fn resolve(file: &Path, path: &str) -> PathBuf {
+    match file.parent() {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+/// E.g. `menus/applications-merged` in the config dirs for `applications.menu`, least important
+/// first.
+fn default_merge_dirs(file: &Path) -> Vec<PathBuf> {
+    let stem = match file.file_stem() {
+        Some(some) => some.to_string_lossy().into_owned(),
+        None => return Vec::new(),
+    };
+
+    xdg::config_dirs()
+        .into_iter()
+        .rev()
+        .map(|dir| dir.join("menus").join(format!("{}-merged", stem)))
+        .collect()
+}
+
+/// The file at the same path in the `menus` directory of a less important config dir, for
+/// `<MergeFile type="parent">`.
+fn parent_file(file: &Path) -> Option<PathBuf> {
+    let menu_dirs: Vec<PathBuf> = xdg::config_dirs()
+        .into_iter()
+        .map(|dir| dir.join("menus"))
+        .collect();
+    let index = menu_dirs.iter().position(|dir| file.starts_with(dir))?;
+    let relative = file.strip_prefix(&menu_dirs[index]).ok()?;
+
+    menu_dirs[(index + 1)..]
+        .iter()
+        .map(|dir| dir.join(relative))
+        .find(|path| path.is_file())
+}
+
+fn rule(element: &Element) -> Option<Rule> {
+    let children = || element.children.iter().filter_map(rule).collect();
+
+    match element.name.as_str() {
+        "Filename" => Some(Rule::Filename(element.text.trim().to_owned())),
+        "Category" => Some(Rule::Category(element.text.trim().to_owned())),
+        "All" => Some(Rule::All),
+        "And" => Some(Rule::And(children())),
+        "Or" => Some(Rule::Or(children())),
+        "Not" => Some(Rule::Not(Box::new(Rule::Or(children())))),
+        _ => None,
+    }
+}
+
+/// The items of a `<Layout>`, separators aren't shown.
+fn layout(element: &Element) -> Vec<LayoutItem> {
+    element
+        .children
+        .iter()
+        .filter_map(|child| {
+            match child.name.as_str() {
+                "Filename" => Some(LayoutItem::Filename(child.text.trim().to_owned())),
+                "Menuname" => Some(LayoutItem::Menuname(child.text.trim().to_owned())),
+                "Merge" => Some(LayoutItem::Merge(child.attribute("type")?.to_owned())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Merge menus with the same name, the later one's contents take precedence.
+fn consolidate(node: &mut Node) {
+    let mut children: Vec<Node> = Vec::new();
+
+    for child in node.children.drain(..) {
+        match children.iter_mut().find(|c| c.name == child.name) {
+            Some(existing) => existing.absorb(child),
+            None => children.push(child),
+        }
+    }
+
+    for child in children.iter_mut() {
+        consolidate(child);
+    }
+
+    node.children = children;
+}
+
+/// The categories of the top-level menus, in the order of the root's layout. Submenus become
+/// sub-categories. The entries the root menu includes itself follow in a category of their own.
+fn categories(root: &Node) -> Vec<Category> {
+    let mut directory_files = DirectoryFiles::default();
+    let mut categories = menus(
+        root,
+        None,
+        &root.directory_dirs,
+        root.default_layout.as_ref(),
+        &mut directory_files,
+    );
+
+    if root.rules.iter().any(|(include, _)| *include) {
+        let mut category =
+            Category::new(root.name.clone(), root.name.clone(), "applications-other");
+        category.rule = root.rule();
+        category.only_unallocated = root.only_unallocated == Some(true);
+        category.layout = layout_ids(root.layout.as_ref().or(root.default_layout.as_ref()));
+        directory::apply(
+            &mut category,
+            &root.directories,
+            &root.directory_dirs,
+            &mut directory_files,
+        );
+        categories.push(category);
+    }
+
+    categories
+}
+
+/// The desktop-file IDs a layout lists, in order.
+fn layout_ids(layout: Option<&Vec<LayoutItem>>) -> Vec<String> {
+    layout
+        .map(|layout| {
+            layout
+                .iter()
+                .filter_map(|item| {
+                    match item {
+                        LayoutItem::Filename(id) => Some(id.clone()),
+                        _ => None,
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The categories of a menu's submenus, in the order of its layout. `default_layout` is the
+/// closest `<DefaultLayout>` of the menu & its parents.
+fn menus(
+    parent: &Node,
+    parent_iden: Option<&str>,
+    directory_dirs: &[PathBuf],
+    default_layout: Option<&Vec<LayoutItem>>,
+    directory_files: &mut DirectoryFiles,
+) -> Vec<Category> {
+    let mut categories: Vec<(&str, Category)> = parent
+        .children
+        .iter()
+        .filter(|node| !node.is_deleted())
+        .map(|node| {
+            let iden = match parent_iden {
+                Some(parent_iden) => format!("{}/{}", parent_iden, node.name),
+                None => node.name.clone(),
+            };
+
+            let mut category = Category::new(iden, node.name.clone(), "applications-other");
+            let default_layout = node.default_layout.as_ref().or(default_layout);
+            category.rule = node.tree_rule();
+            category.only_unallocated = node.only_unallocated == Some(true);
+            category.layout = layout_ids(node.layout.as_ref().or(default_layout));
+
+            // Submenus inherit the directory dirs of their parent.
+            let mut directory_dirs = directory_dirs.to_vec();
+            directory_dirs.extend(node.directory_dirs.iter().cloned());
+
+            directory::apply(
+                &mut category,
+                &node.directories,
+                &directory_dirs,
+                directory_files,
+            );
+            category.children = menus(
+                node,
+                Some(&category.iden),
+                &directory_dirs,
+                default_layout,
+                directory_files,
+            );
+
+            (node.name.as_str(), category)
+        })
+        .collect();
+
+    categories.sort_by_key(|(_, c)| c.name.to_lowercase());
+
+    let layout = match parent.layout.as_ref().or(default_layout) {
+        Some(some) => some,
+        None => return categories.into_iter().map(|(_, c)| c).collect(),
+    };
+
+    // Menus a layout doesn't name are only shown if it merges them in.
+    let mut ordered = Vec::new();
+    let mut merge_at = None;
+
+    for item in layout {
+        match item {
+            LayoutItem::Menuname(name) => {
+                if let Some(i) = categories.iter().position(|(n, _)| *n == name) {
+                    ordered.push(categories.remove(i).1);
+                }
+            },
+            LayoutItem::Merge(kind) if (kind == "menus" || kind == "all") && merge_at.is_none() => {
+                merge_at = Some(ordered.len());
+            },
+            _ => (),
+        }
+    }
+
+    if let Some(at) = merge_at {
+        ordered.splice(at..at, categories.into_iter().map(|(_, c)| c));
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::category;
+    use crate::desktop::{test_entry, DesktopEntry};
+
+    fn entry(id: &str, categories: &str) -> DesktopEntry {
+        let keys = format!("Name={}\nExec={}\nCategories={}\n", id, id, categories);
+        test_entry(id, id, &keys)
+    }
+
+    /// Write the files of a test into a fresh directory, returning the path of the first.
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            env::temp_dir().join(format!("pyroxene-menufile-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+
+        for (name, text) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        dir.join(files[0].0)
+    }
+
+    fn open(test: &str, files: &[(&str, &str)]) -> MenuFile {
+        let path = write_files(test, files);
+        let menu_file = MenuFile::open(&path).unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        menu_file
+    }
+
+    fn names(categories: &[Category]) -> Vec<&str> {
+        categories.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn includes_and_excludes_in_order() {
+        let menu_file = open(
+            "rules",
+            &[(
+                "applications.menu",
+                r#"<!DOCTYPE Menu PUBLIC "-//freedesktop//DTD Menu 1.0//EN"
+                    "http://www.freedesktop.org/standards/menu-spec/1.0/menu.dtd">
+                <Menu>
+                    <Name>Applications</Name>
+                    <Menu>
+                        <Name>Tools</Name>
+                        <Include><Category>Utility</Category></Include>
+                        <Exclude><Filename>calc.desktop</Filename></Exclude>
+                        <Include><Filename>term.desktop</Filename></Include>
+                    </Menu>
+                    <Menu>
+                        <Name>Science</Name>
+                        <Include>
+                            <And>
+                                <Category>Education</Category>
+                                <Not><Category>Math</Category></Not>
+                            </And>
+                            <Or><Filename>calc.desktop</Filename></Or>
+                        </Include>
+                    </Menu>
+                    <Menu>
+                        <Name>Empty</Name>
+                        <Exclude><All/></Exclude>
+                    </Menu>
+                </Menu>"#,
+            )],
+        );
+
+        let categories = menu_file.categories;
+        assert_eq!(names(&categories), vec!["Empty", "Science", "Tools"]);
+
+        let matches = |i: usize, id: &str, c: &str| categories[i].rule.matches(&entry(id, c));
+        assert!(matches(2, "edit.desktop", "Utility;"));
+        assert!(!matches(2, "calc.desktop", "Utility;"));
+        assert!(matches(2, "term.desktop", "System;"));
+        assert!(matches(1, "atlas.desktop", "Education;Geography;"));
+        assert!(!matches(1, "plot.desktop", "Education;Math;"));
+        assert!(matches(1, "calc.desktop", "Utility;"));
+        assert!(!matches(0, "edit.desktop", "Utility;"));
+    }
+
+    #[test]
+    fn submenus() {
+        let menu_file = open(
+            "submenus",
+            &[(
+                "applications.menu",
+                r#"<Menu>
+                    <Name>Applications</Name>
+                    <Menu>
+                        <Name>Development</Name>
+                        <Include><Category>Development</Category></Include>
+                        <Menu>
+                            <Name>Debuggers</Name>
+                            <Include><Category>Debugger</Category></Include>
+                        </Menu>
+                        <Menu>
+                            <Name>Old</Name>
+                            <Include><Category>Legacy</Category></Include>
+                            <Deleted/>
+                        </Menu>
+                    </Menu>
+                    <Menu>
+                        <Name>Development</Name>
+                        <Menu>
+                            <Name>Debuggers</Name>
+                            <Include><Filename>rr.desktop</Filename></Include>
+                        </Menu>
+                    </Menu>
+                </Menu>"#,
+            )],
+        );
+
+        let development = &menu_file.categories[0];
+        assert_eq!(names(&menu_file.categories), vec!["Development"]);
+        assert_eq!(names(&development.children), vec!["Debuggers"]);
+        assert_eq!(development.children[0].iden, "Development/Debuggers");
+
+        // A menu holds the entries of its submenus, but not of deleted ones.
+        assert!(development.rule.matches(&entry("gdb.desktop", "Debugger;")));
+        assert!(development.rule.matches(&entry("rr.desktop", "")));
+        assert!(!development.rule.matches(&entry("old.desktop", "Legacy;")));
+        assert!(development.children[0]
+            .rule
+            .matches(&entry("rr.desktop", "")));
+        assert!(!development.children[0]
+            .rule
+            .matches(&entry("ide.desktop", "Development;")));
+    }
+
+    #[test]
+    fn layout_and_merged_files() {
+        let menu_file = open(
+            "layout",
+            &[
+                (
+                    "applications.menu",
+                    r#"<Menu>
+                        <Name>Applications</Name>
+                        <AppDir>apps</AppDir>
+                        <Layout>
+                            <Menuname>Zebra</Menuname>
+                            <Merge type="menus"/>
+                            <Menuname>Alpha</Menuname>
+                        </Layout>
+                        <Menu><Name>Alpha</Name></Menu>
+                        <Menu><Name>Zebra</Name></Menu>
+                        <MergeFile>merged/extra.menu</MergeFile>
+                        <MergeFile>applications.menu</MergeFile>
+                    </Menu>"#,
+                ),
+                (
+                    "merged/extra.menu",
+                    r#"<Menu>
+                        <Name>Ignored</Name>
+                        <Menu>
+                            <Name>Middle</Name>
+                            <Layout>
+                                <Filename>b.desktop</Filename>
+                                <Filename>a.desktop</Filename>
+                            </Layout>
+                        </Menu>
+                        <Menu><Name>Beta</Name></Menu>
+                    </Menu>"#,
+                ),
+            ],
+        );
+
+        assert_eq!(
+            names(&menu_file.categories),
+            vec!["Zebra", "Beta", "Middle", "Alpha"]
+        );
+        assert_eq!(
+            menu_file.categories[2].layout,
+            vec!["b.desktop", "a.desktop"]
+        );
+        assert_eq!(menu_file.app_dirs.len(), 1);
+        assert!(menu_file.app_dirs[0].ends_with("apps"));
+    }
+
+    #[test]
+    fn root_includes_and_unallocated() {
+        let menu_file = open(
+            "unallocated",
+            &[(
+                "applications.menu",
+                r#"<Menu>
+                    <Name>Applications</Name>
+                    <Include><Filename>help.desktop</Filename></Include>
+                    <Menu>
+                        <Name>Tools</Name>
+                        <Include><Category>Utility</Category></Include>
+                    </Menu>
+                    <Menu>
+                        <Name>More</Name>
+                        <Include><Category>Utility</Category></Include>
+                        <Menu>
+                            <Name>Leftovers</Name>
+                            <OnlyUnallocated/>
+                            <Include><Category>Utility</Category></Include>
+                        </Menu>
+                    </Menu>
+                </Menu>"#,
+            )],
+        );
+
+        let mut categories = menu_file.categories;
+        assert_eq!(names(&categories), vec!["More", "Tools", "Applications"]);
+
+        let entries: Vec<_> = vec![entry("calc.desktop", "Utility;"), entry("help.desktop", "")]
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        category::distribute(&mut categories, &entries);
+
+        let ids = |category: &Category| -> Vec<String> {
+            category.entries.iter().map(|e| e.id.clone()).collect()
+        };
+
+        assert_eq!(ids(&categories[1]), vec!["calc.desktop"]);
+        assert!(ids(&categories[0].children[0]).is_empty());
+        assert_eq!(ids(&categories[2]), vec!["help.desktop"]);
+    }
+}
@@ -0,0 +1,608 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::xdg;
+
+#[derive(Clone, Debug)]
+struct Glob {
+    mime: String,
+    pattern: String,
+    weight: u32,
+    case_sensitive: bool,
+}
+
+#[derive(Clone, Debug)]
+struct MagicSection {
+    priority: u32,
+    mime: String,
+    matchlets: Vec<Matchlet>,
+}
+
+#[derive(Clone, Debug)]
+struct Matchlet {
+    indent: usize,
+    offset: usize,
+    range: usize,
+    value: Vec<u8>,
+    mask: Option<Vec<u8>>,
+}
+
+/// The shared-mime-info database of the XDG data dirs.
+#[derive(Clone, Debug, Default)]
+pub struct MimeDatabase {
+    globs: Vec<Glob>,
+    magic: Vec<MagicSection>,
+    aliases: HashMap<String, String>,
+    parents: HashMap<String, Vec<String>>,
+    magic_extent: usize,
+}
+
+impl MimeDatabase {
+    /// Load `globs2`, `magic`, `aliases` & `subclasses` from the `mime` directory of every data
+    /// dir. Missing files are skipped.
+    pub fn load() -> Self {
+        let mut database = MimeDatabase::default();
+
+        // Least important first so `__NOGLOBS__` can remove globs of less important dirs.
+        for dir in xdg::data_dirs().into_iter().rev() {
+            let dir = dir.join("mime");
+
+            if !dir.is_dir() {
+                continue;
+            }
+
+            if let Ok(text) = fs::read_to_string(dir.join("globs2")) {
+                database.load_globs(&text);
+            }
+
+            if let Ok(bytes) = fs::read(dir.join("magic")) {
+                if let Err(line) = database.load_magic(&bytes) {
+                    println!(
+                        "Warning: Failed to parse {:?} at line {}",
+                        dir.join("magic"),
+                        line
+                    );
+                }
+            }
+
+            if let Ok(text) = fs::read_to_string(dir.join("aliases")) {
+                for (alias, mime) in pairs(&text) {
+                    database.aliases.insert(alias, mime);
+                }
+            }
+
+            if let Ok(text) = fs::read_to_string(dir.join("subclasses")) {
+                for (mime, parent) in pairs(&text) {
+                    let parents = database.parents.entry(mime).or_default();
+
+                    if !parents.contains(&parent) {
+                        parents.push(parent);
+                    }
+                }
+            }
+        }
+
+        database.magic.sort_by_key(|m| Reverse(m.priority));
+        database.magic_extent = database
+            .magic
+            .iter()
+            .flat_map(|section| section.matchlets.iter())
+            .map(|m| m.offset + m.range + m.value.len())
+            .max()
+            .unwrap_or(0);
+
+        database
+    }
+
+    fn load_globs(&mut self, text: &str) {
+        for line in text.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(':');
+            let weight = match fields.next().and_then(|w| w.parse().ok()) {
+                Some(some) => some,
+                None => continue,
+            };
+
+            let (mime, pattern) = match (fields.next(), fields.next()) {
+                (Some(mime), Some(pattern)) => (mime, pattern),
+                _ => continue,
+            };
+
+            let case_sensitive = fields
+                .next()
+                .map(|flags| flags.split(',').any(|f| f == "cs"))
+                .unwrap_or(false);
+
+            if pattern == "__NOGLOBS__" {
+                self.globs.retain(|g| g.mime != mime);
+                continue;
+            }
+
+            self.globs.push(Glob {
+                mime: mime.to_owned(),
+                pattern: pattern.to_owned(),
+                weight,
+                case_sensitive,
+            });
+        }
+    }
+
+    /// Parse a binary `magic` file. On failure the line number is returned.
+    fn load_magic(&mut self, bytes: &[u8]) -> Result<(), usize> {
+        const HEADER: &[u8] = b"MIME-Magic\0\n";
+
+        if !bytes.starts_with(HEADER) {
+            return Err(1);
+        }
+
+        let mut pos = HEADER.len();
+        let mut line = 2;
+
+        while pos < bytes.len() {
+            if bytes[pos] == b'[' {
+                let end = pos + bytes[pos..].iter().position(|b| *b == b'\n').ok_or(line)?;
+
+                if end < pos + 2 || bytes[end - 1] != b']' {
+                    return Err(line);
+                }
+
+                let header = String::from_utf8_lossy(&bytes[(pos + 1)..(end - 1)]).into_owned();
+                let split = header.find(':').ok_or(line)?;
+
+                self.magic.push(MagicSection {
+                    priority: header[..split].parse().map_err(|_| line)?,
+                    mime: header[(split + 1)..].to_owned(),
+                    matchlets: Vec::new(),
+                });
+
+                pos = end + 1;
+                line += 1;
+                continue;
+            }
+
+            let number = |pos: &mut usize| {
+                let start = *pos;
+
+                while *pos < bytes.len() && bytes[*pos].is_ascii_digit() {
+                    *pos += 1;
+                }
+
+                std::str::from_utf8(&bytes[start..*pos])
+                    .ok()
+                    .and_then(|n| n.parse::<usize>().ok())
+            };
+
+            let indent = number(&mut pos).unwrap_or(0);
+
+            if bytes.get(pos) != Some(&b'>') {
+                return Err(line);
+            }
+
+            pos += 1;
+            let offset = number(&mut pos).ok_or(line)?;
+
+            if bytes.get(pos) != Some(&b'=') || pos + 3 > bytes.len() {
+                return Err(line);
+            }
+
+            let length = ((bytes[pos + 1] as usize) << 8) | bytes[pos + 2] as usize;
+            pos += 3;
+            let mut value = bytes.get(pos..(pos + length)).ok_or(line)?.to_vec();
+            pos += length;
+            let mut mask = None;
+            let mut word_size = 1;
+            let mut range = 1;
+
+            loop {
+                match bytes.get(pos) {
+                    Some(b'&') => {
+                        mask = Some(
+                            bytes
+                                .get((pos + 1)..(pos + 1 + length))
+                                .ok_or(line)?
+                                .to_vec(),
+                        );
+                        pos += 1 + length;
+                    },
+                    Some(b'~') => {
+                        pos += 1;
+                        word_size = number(&mut pos).ok_or(line)?;
+                    },
+                    Some(b'+') => {
+                        pos += 1;
+                        range = number(&mut pos).ok_or(line)?;
+                    },
+                    Some(b'\n') => {
+                        pos += 1;
+                        break;
+                    },
+                    _ => return Err(line),
+                }
+            }
+
+            // Values are stored big endian, swap words of host order types on little endian.
+            if cfg!(target_endian = "little") && word_size > 1 {
+                for chunk in value.chunks_mut(word_size) {
+                    chunk.reverse();
+                }
+
+                if let Some(mask) = mask.as_mut() {
+                    for chunk in mask.chunks_mut(word_size) {
+                        chunk.reverse();
+                    }
+                }
+            }
+
+            self.magic.last_mut().ok_or(line)?.matchlets.push(Matchlet {
+                indent,
+                offset,
+                range,
+                value,
+                mask,
+            });
+
+            line += 1;
+        }
+
+        Ok(())
+    }
+
+    /// The canonical name of a MIME type that may be an alias.
+    pub fn unalias(&self, mime: &str) -> String {
+        self.aliases
+            .get(mime)
+            .cloned()
+            .unwrap_or_else(|| mime.to_owned())
+    }
+
+    /// The direct parents of a MIME type, including the implicit `text/plain` &
+    /// `application/octet-stream`.
+    pub fn parents(&self, mime: &str) -> Vec<String> {
+        let mut parents = self.parents.get(mime).cloned().unwrap_or_default();
+
+        if mime.starts_with("text/") && mime != "text/plain" {
+            parents.push(String::from("text/plain"));
+        }
+
+        if !mime.starts_with("inode/") && mime != "application/octet-stream" {
+            parents.push(String::from("application/octet-stream"));
+        }
+
+        parents
+    }
+
+    /// `mime` followed by all of its ancestors, closest first.
+    pub fn ancestors(&self, mime: &str) -> Vec<String> {
+        let mut ancestors = vec![self.unalias(mime)];
+        let mut i = 0;
+
+        while i < ancestors.len() {
+            for parent in self.parents(&ancestors[i]) {
+                let parent = self.unalias(&parent);
+
+                if !ancestors.contains(&parent) {
+                    ancestors.push(parent);
+                }
+            }
+
+            i += 1;
+        }
+
+        // Keep the catch-all type last no matter how deep the hierarchy is.
+        if let Some(i) = ancestors
+            .iter()
+            .position(|a| a == "application/octet-stream")
+        {
+            let octet_stream = ancestors.remove(i);
+            ancestors.push(octet_stream);
+        }
+
+        ancestors
+    }
+
+    /// Is `mime` equal to or a subclass of `parent`?
+    pub fn is_subclass(&self, mime: &str, parent: &str) -> bool {
+        let parent = self.unalias(parent);
+        self.ancestors(mime).contains(&parent)
+    }
+
+    /// The MIME types whose best globs match a file name.
+    fn glob_matches(&self, name: &str) -> Vec<&str> {
+        let lower = name.to_lowercase();
+        let mut best: Option<(u32, usize)> = None;
+        let mut matches = Vec::new();
+
+        for glob in self.globs.iter() {
+            let matched = if glob.case_sensitive {
+                glob_match(&glob.pattern, name)
+            } else {
+                glob_match(&glob.pattern.to_lowercase(), &lower)
+            };
+
+            if !matched {
+                continue;
+            }
+
+            let rank = (glob.weight, glob.pattern.len());
+
+            if best.map(|b| rank > b).unwrap_or(true) {
+                best = Some(rank);
+                matches.clear();
+            }
+
+            if Some(rank) == best && !matches.contains(&glob.mime.as_str()) {
+                matches.push(glob.mime.as_str());
+            }
+        }
+
+        matches
+    }
+
+    /// The MIME type of the highest priority magic section matching `data`.
+    fn magic_match(&self, data: &[u8]) -> Option<&str> {
+        self.magic
+            .iter()
+            .find(|section| matchlets_match(&section.matchlets, data))
+            .map(|section| section.mime.as_str())
+    }
+
+    /// Guess the MIME type of a file from its name, falling back to its contents.
+    pub fn type_of<P: AsRef<Path>>(&self, path: P) -> String {
+        let path = path.as_ref();
+
+        let metadata = match path.metadata() {
+            Ok(ok) => ok,
+            Err(_) => return String::from("application/octet-stream"),
+        };
+
+        if metadata.is_dir() {
+            return String::from("inode/directory");
+        }
+
+        let globbed = path
+            .file_name()
+            .map(|n| self.glob_matches(&n.to_string_lossy()))
+            .unwrap_or_default();
+
+        if globbed.len() == 1 {
+            return self.unalias(globbed[0]);
+        }
+
+        if metadata.len() == 0 {
+            return String::from("application/x-zerosize");
+        }
+
+        let mut data = Vec::new();
+
+        if let Ok(file) = File::open(path) {
+            let _ = file
+                .take(self.magic_extent.max(512) as u64)
+                .read_to_end(&mut data);
+        }
+
+        if let Some(sniffed) = self.magic_match(&data) {
+            if let Some(mime) = globbed.iter().find(|g| self.is_subclass(g, sniffed)) {
+                return self.unalias(mime);
+            }
+
+            if globbed.is_empty() {
+                return self.unalias(sniffed);
+            }
+        }
+
+        if let Some(mime) = globbed.first() {
+            return self.unalias(mime);
+        }
+
+        if looks_like_text(&data) {
+            String::from("text/plain")
+        } else {
+            String::from("application/octet-stream")
+        }
+    }
+}
+
+/// Does any top level matchlet, and one of its children if it has any, match `data`?
+fn matchlets_match(matchlets: &[Matchlet], data: &[u8]) -> bool {
+    let mut i = 0;
+
+    while i < matchlets.len() {
+        let end = subtree_end(matchlets, i);
+
+        if matchlet_tree_match(&matchlets[i..end], data) {
+            return true;
+        }
+
+        i = end;
+    }
+
+    false
+}
+
+/// `matchlets[0]` is the root, the rest are its descendants.
+fn matchlet_tree_match(matchlets: &[Matchlet], data: &[u8]) -> bool {
+    if !matchlet_match(&matchlets[0], data) {
+        return false;
+    }
+
+    if matchlets.len() == 1 {
+        return true;
+    }
+
+    matchlets_match(&matchlets[1..], data)
+}
+
+fn subtree_end(matchlets: &[Matchlet], root: usize) -> usize {
+    let indent = matchlets[root].indent;
+    let mut end = root + 1;
+
+    while end < matchlets.len() && matchlets[end].indent > indent {
+        end += 1;
+    }
+
+    end
+}
+
+fn matchlet_match(matchlet: &Matchlet, data: &[u8]) -> bool {
+    let len = matchlet.value.len();
+
+    (matchlet.offset..(matchlet.offset + matchlet.range)).any(|start| {
+        let window = match data.get(start..(start + len)) {
+            Some(some) => some,
+            None => return false,
+        };
+
+        match matchlet.mask.as_ref() {
+            Some(mask) => {
+                window
+                    .iter()
+                    .zip(matchlet.value.iter())
+                    .zip(mask.iter())
+                    .all(|((d, v), m)| d & m == v & m)
+            },
+            None => window == matchlet.value.as_slice(),
+        }
+    })
+}
+
+/// Text is assumed if the data is UTF-8 without control characters other than whitespace.
+fn looks_like_text(data: &[u8]) -> bool {
+    let text = match std::str::from_utf8(data) {
+        Ok(ok) => ok,
+        // The read may have cut a character in half.
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or("")
+        },
+        Err(_) => return false,
+    };
+
+    !text.chars().any(|c| c.is_control() && !c.is_whitespace())
+}
+
+/// Match a shell glob supporting `*`, `?` & `[...]` against a whole file name.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue;
+            },
+            Some('?') => Some(p + 1),
+            Some('[') => {
+                match class_match(&pattern, p, name[n]) {
+                    Some((true, next)) => Some(next),
+                    _ => None,
+                }
+            },
+            Some(c) if *c == name[n] => Some(p + 1),
+            _ => None,
+        };
+
+        match step {
+            Some(next) => {
+                p = next;
+                n += 1;
+            },
+            None => {
+                match backtrack {
+                    Some((star, matched)) => {
+                        p = star + 1;
+                        n = matched + 1;
+                        backtrack = Some((star, matched + 1));
+                    },
+                    None => return false,
+                }
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Match a `[...]` class starting at `pattern[start]`, returning whether it matched and the index
+/// after the class. `None` if the class isn't closed.
+fn class_match(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some('!') | Some('^'));
+
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        let current = *pattern.get(i)?;
+
+        if current == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).map(|e| *e != ']') == Some(true) {
+            if current <= c && c <= pattern[i + 2] {
+                matched = true;
+            }
+
+            i += 3;
+        } else {
+            if current == c {
+                matched = true;
+            }
+
+            i += 1;
+        }
+
+        first = false;
+    }
+}
+
+fn pairs(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some((fields.next()?.to_owned(), fields.next()?.to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_section() {
+        let mut database = MimeDatabase::default();
+        let bytes = b"MIME-Magic\0\n[50:image/png]\n>0=\0\x04\x89PNG\n";
+
+        assert_eq!(database.load_magic(bytes), Ok(()));
+        assert_eq!(database.magic[0].priority, 50);
+        assert_eq!(database.magic[0].mime, "image/png");
+        assert_eq!(database.magic[0].matchlets[0].value, b"\x89PNG");
+    }
+
+    #[test]
+    fn malformed_magic_section() {
+        for bytes in [
+            &b"MIME-Magic\0\n[\n"[..],
+            b"MIME-Magic\0\n[]\n",
+            b"MIME-Magic\0\n[50:image/png\n",
+            b"MIME-Magic\0\n[50:image/png]",
+        ] {
+            assert_eq!(MimeDatabase::default().load_magic(bytes), Err(2));
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::keyfile::{KeyFile, KeyFileErr};
+use crate::mime::MimeDatabase;
+use crate::{discovery, xdg};
+
+/// The associations between MIME types & applications of the whole `mimeapps.list` cascade.
+#[derive(Clone, Debug, Default)]
+pub struct MimeApps {
+    defaults: HashMap<String, Vec<String>>,
+    added: HashMap<String, Vec<String>>,
+    /// For each removed association, the index of the most important source removing it.
+    removed: HashMap<String, HashMap<String, usize>>,
+    /// The application dirs with the index of their `mimeinfo.cache` among the sources.
+    cache_indices: Vec<(PathBuf, usize)>,
+}
+
+/// The `mimeapps.list` & `mimeinfo.cache` files to read, most important first. Each directory
+/// contributes the desktop specific `$desktop-mimeapps.list` files before `mimeapps.list`, data
+/// dirs are followed by their `mimeinfo.cache`.
+pub fn sources(desktops: &[String]) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let lists = |paths: &mut Vec<PathBuf>, dir: &PathBuf| {
+        for desktop in desktops {
+            paths.push(dir.join(format!("{}-mimeapps.list", desktop.to_lowercase())));
+        }
+
+        paths.push(dir.join("mimeapps.list"));
+    };
+
+    for dir in xdg::config_dirs() {
+        lists(&mut paths, &dir);
+    }
+
+    for dir in discovery::application_dirs() {
+        lists(&mut paths, &dir);
+        paths.push(dir.join("mimeinfo.cache"));
+    }
+
+    paths
+}
+
+/// The `mimeapps.list` pyroxene writes to, `$XDG_CONFIG_HOME/mimeapps.list`.
+pub fn user_list_path() -> PathBuf {
+    xdg::config_home().join("mimeapps.list")
+}
+
+impl MimeApps {
+    pub fn load(desktops: &[String]) -> Self {
+        Self::read(sources(desktops))
+    }
+
+    /// Read `mimeapps.list` & `mimeinfo.cache` files, most important first. Missing files are
+    /// skipped.
+    fn read(sources: Vec<PathBuf>) -> Self {
+        let mut mime_apps = MimeApps::default();
+
+        for (index, path) in sources.into_iter().enumerate() {
+            if path.file_name().map(|n| n == "mimeinfo.cache") == Some(true) {
+                if let Some(dir) = path.parent() {
+                    mime_apps.cache_indices.push((dir.to_path_buf(), index));
+                }
+            }
+
+            let key_file = match KeyFile::open(&path) {
+                Ok(ok) => ok,
+                Err(_) => continue,
+            };
+
+            if path.file_name().map(|n| n == "mimeinfo.cache") == Some(true) {
+                if let Some(group) = key_file.group("MIME Cache") {
+                    for entry in group.entries.iter() {
+                        mime_apps.add(&entry.key, group.list(&entry.key).unwrap_or_default());
+                    }
+                }
+
+                continue;
+            }
+
+            if let Some(group) = key_file.group("Default Applications") {
+                for entry in group.entries.iter() {
+                    let defaults = mime_apps.defaults.entry(entry.key.clone()).or_default();
+
+                    for id in group.list(&entry.key).unwrap_or_default() {
+                        if !defaults.contains(&id) {
+                            defaults.push(id);
+                        }
+                    }
+                }
+            }
+
+            if let Some(group) = key_file.group("Added Associations") {
+                for entry in group.entries.iter() {
+                    mime_apps.add(&entry.key, group.list(&entry.key).unwrap_or_default());
+                }
+            }
+
+            // Removals apply to the less important files that follow.
+            if let Some(group) = key_file.group("Removed Associations") {
+                for entry in group.entries.iter() {
+                    let removed = mime_apps.removed.entry(entry.key.clone()).or_default();
+
+                    for id in group.list(&entry.key).unwrap_or_default() {
+                        removed.entry(id).or_insert(index);
+                    }
+                }
+            }
+        }
+
+        mime_apps
+    }
+
+    fn add(&mut self, mime: &str, ids: Vec<String>) {
+        let removed = self.removed.get(mime);
+        let added = self.added.entry(mime.to_owned()).or_default();
+
+        for id in ids {
+            if !added.contains(&id) && removed.map(|r| r.contains_key(&id)) != Some(true) {
+                added.push(id);
+            }
+        }
+    }
+
+    /// The desktop-file IDs listed as the default for `mime`, most important first.
+    pub fn defaults(&self, mime: &str) -> &[String] {
+        self.defaults.get(mime).map(|d| d.as_slice()).unwrap_or(&[])
+    }
+
+    /// The desktop-file IDs associated with `mime` that weren't removed, most preferred first.
+    pub fn associations(&self, mime: &str) -> &[String] {
+        self.added.get(mime).map(|a| a.as_slice()).unwrap_or(&[])
+    }
+
+    /// Was the `MimeType` association of `id` with `mime` removed? Only files more important than
+    /// the `mimeinfo.cache` of the directory of `source`, the desktop file, remove it.
+    pub fn is_removed(&self, mime: &str, id: &str, source: &Path) -> bool {
+        let cache_index = self
+            .cache_indices
+            .iter()
+            .find(|(dir, _)| source.starts_with(dir))
+            .map(|(_, index)| *index)
+            .unwrap_or(usize::MAX);
+
+        self.removed
+            .get(mime)
+            .and_then(|r| r.get(id))
+            .map(|index| *index < cache_index)
+            .unwrap_or(false)
+    }
+
+    /// The first installed default for `mime`, falling back to the most preferred association.
+    pub fn default_for<F: Fn(&str) -> bool>(&self, mime: &str, installed: F) -> Option<String> {
+        self.defaults(mime)
+            .iter()
+            .chain(self.associations(mime).iter())
+            .find(|id| installed(id))
+            .cloned()
+    }
+}
+
+/// Set the default application for `mime` in the user's `mimeapps.list`. The rest of the file is
+/// kept as is.
+pub fn set_default(mime: &str, id: &str) -> Result<(), KeyFileErr> {
+    let path = user_list_path();
+    let mut key_file = match KeyFile::open(&path) {
+        Ok(ok) => ok,
+        Err(KeyFileErr::IO(e)) if e.kind() == io::ErrorKind::NotFound => KeyFile::default(),
+        Err(e) => return Err(e),
+    };
+
+    key_file
+        .group_or_insert("Default Applications")
+        .set_list(mime, &[id]);
+    key_file.save(path)
+}
+
+/// Print the default application & associations of `mime`, or make `id` its default. Returns
+/// `false` on failure.
+pub fn run(mime: &str, id: Option<&str>, desktops: &[String]) -> bool {
+    let mime = MimeDatabase::load().unalias(mime);
+    let installed: HashSet<String> = discovery::desktop_files()
+        .into_iter()
+        .map(|file| file.id)
+        .collect();
+
+    match id {
+        Some(id) => {
+            if !installed.contains(id) {
+                println!("Unknown desktop-file ID: {}", id);
+                return false;
+            }
+
+            match set_default(&mime, id) {
+                Ok(_) => {
+                    println!("{} is now the default for {}", id, mime);
+                    true
+                },
+                Err(e) => {
+                    println!("Failed to write {}: {}", user_list_path().display(), e);
+                    false
+                },
+            }
+        },
+        None => {
+            let mime_apps = MimeApps::load(desktops);
+
+            match mime_apps.default_for(&mime, |id| installed.contains(id)) {
+                Some(default) => println!("Default: {}", default),
+                None => println!("No default application for {}", mime),
+            }
+
+            let associations: Vec<_> = mime_apps
+                .associations(&mime)
+                .iter()
+                .filter(|id| installed.contains(id.as_str()))
+                .map(|id| id.as_str())
+                .collect();
+
+            if !associations.is_empty() {
+                println!("Associations: {}", associations.join(", "));
+            }
+
+            true
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn removals_apply_to_less_important_files() {
+        let dir = env::temp_dir().join(format!("pyroxene-mimeapps-{}", std::process::id()));
+        let files = [
+            (
+                "config/mimeapps.list",
+                "[Added Associations]\ntext/plain=user-editor.desktop;\n",
+            ),
+            (
+                "xdg/mimeapps.list",
+                "[Removed Associations]\ntext/plain=user-editor.desktop;vim.desktop;\n",
+            ),
+            (
+                "applications/mimeinfo.cache",
+                "[MIME Cache]\ntext/plain=vim.desktop;gedit.desktop;\n",
+            ),
+            (
+                "other/applications/mimeapps.list",
+                "[Removed Associations]\ntext/plain=gedit.desktop;\n",
+            ),
+        ];
+
+        for (name, text) in files.iter() {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        let mime_apps = MimeApps::read(vec![
+            dir.join("config/mimeapps.list"),
+            dir.join("xdg/mimeapps.list"),
+            dir.join("applications/mimeapps.list"),
+            dir.join("applications/mimeinfo.cache"),
+            dir.join("other/applications/mimeapps.list"),
+            dir.join("other/applications/mimeinfo.cache"),
+        ]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            mime_apps.associations("text/plain"),
+            &["user-editor.desktop", "gedit.desktop"]
+        );
+
+        let removed = |id, source: &str| mime_apps.is_removed("text/plain", id, &dir.join(source));
+        assert!(removed("vim.desktop", "applications/vim.desktop"));
+        assert!(!removed("gedit.desktop", "applications/gedit.desktop"));
+        assert!(removed("gedit.desktop", "other/applications/gedit.desktop"));
+        assert!(removed(
+            "gedit.desktop",
+            "other/applications/kde/gedit.desktop"
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use crate::keyfile::{KeyFile, KeyFileErr};
+use crate::{discovery, xdg};
+
+#[derive(Clone, Debug)]
+pub enum Edit {
+    Name(String),
+    Categories(Vec<String>),
+    NoDisplay(bool),
+}
+
+/// Where the override of a desktop-file ID is written. `$XDG_DATA_HOME` is the most important data
+/// dir so the override shadows the original file.
+pub fn override_path(id: &str) -> PathBuf {
+    xdg::data_home().join("applications").join(id)
+}
+
+/// Apply the edits to a copy of a desktop file and write it to the override path. Everything the
+/// edits don't touch is written as it was.
+pub fn write(document: &KeyFile, id: &str, edits: &[Edit]) -> Result<PathBuf, KeyFileErr> {
+    let mut document = document.clone();
+    let group = document.group_or_insert("Desktop Entry");
+
+    for edit in edits {
+        match edit {
+            Edit::Name(name) => {
+                group.set_string("Name", name);
+                // Translations would be shown instead of the new name.
+                group.retain(|e| e.key != "Name" || e.locale.is_none());
+            },
+            Edit::Categories(categories) => group.set_list("Categories", categories),
+            Edit::NoDisplay(no_display) => group.set_boolean("NoDisplay", *no_display),
+        }
+    }
+
+    let path = override_path(id);
+    document.save(&path)?;
+    Ok(path)
+}
+
+/// Write an override of the desktop file with the ID `id`. Returns `false` on failure.
+pub fn run(id: &str, edits: &[Edit]) -> bool {
+    let file = match discovery::desktop_files().into_iter().find(|f| f.id == id) {
+        Some(some) => some,
+        None => {
+            println!("Unknown desktop-file ID: {}", id);
+            return false;
+        },
+    };
+
+    let result = KeyFile::open(&file.path).and_then(|document| write(&document, id, edits));
+
+    match result {
+        Ok(path) => {
+            println!("Wrote {}", path.display());
+            true
+        },
+        Err(e) => {
+            println!("Failed to override {}: {}", file.path.display(), e);
+            false
+        },
+    }
+}
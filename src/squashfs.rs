@@ -0,0 +1,715 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::{fmt, io};
+
+use flate2::read::ZlibDecoder;
+use ruzstd::decoding::StreamingDecoder;
+
+const MAGIC: u32 = 0x7371_7368;
+const COMPRESSION_GZIP: u16 = 1;
+const COMPRESSION_XZ: u16 = 4;
+const COMPRESSION_ZSTD: u16 = 6;
+const MIN_BLOCK_SIZE: u32 = 4096;
+const MAX_BLOCK_SIZE: u32 = 1024 * 1024;
+const NO_FRAGMENT: u32 = 0xffff_ffff;
+const MAX_SYMLINKS: usize = 16;
+
+#[derive(Debug)]
+pub enum SquashFsErr {
+    InvalidImage,
+    UnsupportedCompression(u16),
+    NotFound(String),
+    NotAFile(String),
+    IO(io::Error),
+}
+
+impl fmt::Display for SquashFsErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SquashFsErr::InvalidImage => write!(f, "not a valid squashfs image"),
+            SquashFsErr::UnsupportedCompression(id) => {
+                match compression_name(*id) {
+                    Some(name) => write!(f, "unsupported squashfs compression: {}", name),
+                    None => write!(f, "unsupported squashfs compression: {}", id),
+                }
+            },
+            SquashFsErr::NotFound(path) => write!(f, "{} not found in the image", path),
+            SquashFsErr::NotAFile(path) => write!(f, "{} is not a file", path),
+            SquashFsErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for SquashFsErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SquashFsErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// The name of a compression ID of the superblock.
+fn compression_name(id: u16) -> Option<&'static str> {
+    match id {
+        COMPRESSION_GZIP => Some("gzip"),
+        2 => Some("lzma"),
+        3 => Some("lzo"),
+        COMPRESSION_XZ => Some("xz"),
+        5 => Some("lz4"),
+        COMPRESSION_ZSTD => Some("zstd"),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Inode {
+    Directory {
+        block_index: u32,
+        block_offset: u16,
+        file_size: u32,
+    },
+    File {
+        blocks_start: u64,
+        file_size: u64,
+        fragment: u32,
+        fragment_offset: u32,
+        block_sizes: Vec<u32>,
+    },
+    Symlink(String),
+    Other,
+}
+
+/// A read-only squashfs 4.0 image, e.g. the one embedded in an AppImage. Gzip, xz & zstd
+/// compression are supported.
+pub struct SquashFs {
+    file: File,
+    /// Where the image starts within the file.
+    offset: u64,
+    compression: u16,
+    block_size: u32,
+    root_inode: u64,
+    inode_table: u64,
+    directory_table: u64,
+    fragment_table: u64,
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16, SquashFsErr> {
+    let bytes = data.get(pos..(pos + 2)).ok_or(SquashFsErr::InvalidImage)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32, SquashFsErr> {
+    let bytes = data.get(pos..(pos + 4)).ok_or(SquashFsErr::InvalidImage)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn u64_at(data: &[u8], pos: usize) -> Result<u64, SquashFsErr> {
+    Ok(u32_at(data, pos)? as u64 | (u32_at(data, pos + 4)? as u64) << 32)
+}
+
+impl SquashFs {
+    /// Open the image starting at `offset` within `file`.
+    pub fn open(file: File, offset: u64) -> Result<Self, SquashFsErr> {
+        let mut superblock = [0; 96];
+        file.read_exact_at(&mut superblock, offset)
+            .map_err(SquashFsErr::IO)?;
+
+        if u32_at(&superblock, 0)? != MAGIC || u16_at(&superblock, 28)? != 4 {
+            return Err(SquashFsErr::InvalidImage);
+        }
+
+        let compression = u16_at(&superblock, 20)?;
+
+        match compression {
+            COMPRESSION_GZIP | COMPRESSION_XZ | COMPRESSION_ZSTD => (),
+            _ => return Err(SquashFsErr::UnsupportedCompression(compression)),
+        }
+
+        let block_size = u32_at(&superblock, 12)?;
+
+        if !block_size.is_power_of_two() || !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&block_size)
+        {
+            return Err(SquashFsErr::InvalidImage);
+        }
+
+        Ok(SquashFs {
+            file,
+            offset,
+            compression,
+            block_size,
+            root_inode: u64_at(&superblock, 32)?,
+            inode_table: u64_at(&superblock, 64)?,
+            directory_table: u64_at(&superblock, 72)?,
+            fragment_table: u64_at(&superblock, 80)?,
+        })
+    }
+
+    fn read_at(&self, pos: u64, len: usize) -> Result<Vec<u8>, SquashFsErr> {
+        let pos = self
+            .offset
+            .checked_add(pos)
+            .ok_or(SquashFsErr::InvalidImage)?;
+        let mut data = vec![0; len];
+        self.file
+            .read_exact_at(&mut data, pos)
+            .map_err(SquashFsErr::IO)?;
+        Ok(data)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, SquashFsErr> {
+        let mut out = Vec::with_capacity(self.block_size as usize);
+
+        let decompressed = match self.compression {
+            COMPRESSION_XZ => lzma_rs::xz_decompress(&mut &data[..], &mut out).is_ok(),
+            COMPRESSION_ZSTD => {
+                StreamingDecoder::new(data)
+                    .is_ok_and(|mut decoder| decoder.read_to_end(&mut out).is_ok())
+            },
+            _ => ZlibDecoder::new(data).read_to_end(&mut out).is_ok(),
+        };
+
+        if !decompressed {
+            return Err(SquashFsErr::InvalidImage);
+        }
+
+        Ok(out)
+    }
+
+    /// Read `len` bytes of the metadata starting `offset` bytes into the block at `pos`.
+    fn read_metadata(
+        &self,
+        mut pos: u64,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, SquashFsErr> {
+        let mut data = Vec::new();
+
+        while data.len() < offset + len {
+            let header = self.read_at(pos, 2)?;
+            let header = u16::from_le_bytes([header[0], header[1]]);
+            let size = (header & 0x7fff) as usize;
+            let block = self.read_at(pos + 2, size)?;
+
+            if header & 0x8000 == 0 {
+                data.extend(self.decompress(&block)?);
+            } else {
+                data.extend(block);
+            }
+
+            pos += 2 + size as u64;
+        }
+
+        Ok(data[offset..(offset + len)].to_vec())
+    }
+
+    fn read_inode(&self, inode_ref: u64) -> Result<Inode, SquashFsErr> {
+        let pos = self
+            .inode_table
+            .checked_add(inode_ref >> 16)
+            .ok_or(SquashFsErr::InvalidImage)?;
+        let offset = (inode_ref & 0xffff) as usize;
+        // Large enough for the fixed part of every inode type.
+        let data = self.read_metadata(pos, offset, 56)?;
+
+        match u16_at(&data, 0)? {
+            1 => {
+                Ok(Inode::Directory {
+                    block_index: u32_at(&data, 16)?,
+                    file_size: u16_at(&data, 24)? as u32,
+                    block_offset: u16_at(&data, 26)?,
+                })
+            },
+            8 => {
+                Ok(Inode::Directory {
+                    file_size: u32_at(&data, 20)?,
+                    block_index: u32_at(&data, 24)?,
+                    block_offset: u16_at(&data, 34)?,
+                })
+            },
+            kind @ 2 | kind @ 9 => {
+                let (blocks_start, file_size, fragment, fragment_offset, sizes_start) = if kind == 2
+                {
+                    (
+                        u32_at(&data, 16)? as u64,
+                        u32_at(&data, 28)? as u64,
+                        u32_at(&data, 20)?,
+                        u32_at(&data, 24)?,
+                        32,
+                    )
+                } else {
+                    (
+                        u64_at(&data, 16)?,
+                        u64_at(&data, 24)?,
+                        u32_at(&data, 44)?,
+                        u32_at(&data, 48)?,
+                        56,
+                    )
+                };
+
+                let block_size = self.block_size as u64;
+                let block_count = if fragment == NO_FRAGMENT {
+                    file_size.div_ceil(block_size)
+                } else {
+                    file_size / block_size
+                };
+
+                let block_count =
+                    usize::try_from(block_count).map_err(|_| SquashFsErr::InvalidImage)?;
+                let sizes_len = block_count
+                    .checked_mul(4)
+                    .and_then(|len| len.checked_add(sizes_start))
+                    .ok_or(SquashFsErr::InvalidImage)?;
+
+                let data = self.read_metadata(pos, offset, sizes_len)?;
+                let mut block_sizes = Vec::with_capacity(block_count);
+
+                for i in 0..block_count {
+                    block_sizes.push(u32_at(&data, sizes_start + i * 4)?);
+                }
+
+                Ok(Inode::File {
+                    blocks_start,
+                    file_size,
+                    fragment,
+                    fragment_offset,
+                    block_sizes,
+                })
+            },
+            3 | 10 => {
+                let target_size = u32_at(&data, 20)? as usize;
+                let data = self.read_metadata(pos, offset, 24 + target_size)?;
+                Ok(Inode::Symlink(
+                    String::from_utf8_lossy(&data[24..]).into_owned(),
+                ))
+            },
+            _ => Ok(Inode::Other),
+        }
+    }
+
+    /// The names & inode references of a directory's entries.
+    fn read_dir(&self, inode: &Inode) -> Result<Vec<(String, u64)>, SquashFsErr> {
+        let (block_index, block_offset, file_size) = match inode {
+            Inode::Directory {
+                block_index,
+                block_offset,
+                file_size,
+            } => (*block_index, *block_offset, *file_size),
+            _ => return Err(SquashFsErr::InvalidImage),
+        };
+
+        // The size includes the implicit `.` & `..` entries.
+        if file_size <= 3 {
+            return Ok(Vec::new());
+        }
+
+        let pos = self
+            .directory_table
+            .checked_add(block_index as u64)
+            .ok_or(SquashFsErr::InvalidImage)?;
+        let data = self.read_metadata(pos, block_offset as usize, file_size as usize - 3)?;
+
+        let mut entries = Vec::new();
+        let mut pos = 0;
+
+        while pos + 12 <= data.len() {
+            let count = u32_at(&data, pos)? as usize + 1;
+            let start = u32_at(&data, pos + 4)? as u64;
+            pos += 12;
+
+            for _ in 0..count {
+                let offset = u16_at(&data, pos)? as u64;
+                let name_size = u16_at(&data, pos + 6)? as usize + 1;
+                let name = data
+                    .get((pos + 8)..(pos + 8 + name_size))
+                    .ok_or(SquashFsErr::InvalidImage)?;
+                entries.push((
+                    String::from_utf8_lossy(name).into_owned(),
+                    (start << 16) | offset,
+                ));
+                pos += 8 + name_size;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// The names of the entries in the root directory.
+    pub fn root_entries(&self) -> Result<Vec<String>, SquashFsErr> {
+        let root = self.read_inode(self.root_inode)?;
+        Ok(self
+            .read_dir(&root)?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    /// Find the inode at `path`, following symlinks.
+    fn lookup(&self, path: &str) -> Result<Inode, SquashFsErr> {
+        let mut remaining: Vec<String> = path
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(|c| c.to_owned())
+            .rev()
+            .collect();
+        let mut current: Vec<String> = Vec::new();
+        let mut inode = self.read_inode(self.root_inode)?;
+        let mut symlinks = 0;
+
+        while let Some(component) = remaining.pop() {
+            match component.as_str() {
+                "." => continue,
+                ".." => {
+                    current.pop();
+                    remaining.extend(current.drain(..).rev());
+                    inode = self.read_inode(self.root_inode)?;
+                    continue;
+                },
+                _ => (),
+            }
+
+            let inode_ref = self
+                .read_dir(&inode)
+                .map_err(|_| SquashFsErr::NotFound(path.to_owned()))?
+                .into_iter()
+                .find(|(name, _)| *name == component)
+                .map(|(_, inode_ref)| inode_ref)
+                .ok_or_else(|| SquashFsErr::NotFound(path.to_owned()))?;
+
+            match self.read_inode(inode_ref)? {
+                Inode::Symlink(target) => {
+                    symlinks += 1;
+
+                    if symlinks > MAX_SYMLINKS {
+                        return Err(SquashFsErr::NotFound(path.to_owned()));
+                    }
+
+                    // Resolve the target from the root, relative targets from the link's directory.
+                    if !target.starts_with('/') {
+                        remaining.extend(
+                            target
+                                .split('/')
+                                .filter(|c| !c.is_empty())
+                                .map(|c| c.to_owned())
+                                .rev(),
+                        );
+                        remaining.extend(current.drain(..).rev());
+                    } else {
+                        current.clear();
+                        remaining.extend(
+                            target
+                                .split('/')
+                                .filter(|c| !c.is_empty())
+                                .map(|c| c.to_owned())
+                                .rev(),
+                        );
+                    }
+
+                    inode = self.read_inode(self.root_inode)?;
+                },
+                next => {
+                    current.push(component);
+                    inode = next;
+                },
+            }
+        }
+
+        Ok(inode)
+    }
+
+    /// The contents of the file at `path`, following symlinks.
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, SquashFsErr> {
+        let (blocks_start, file_size, fragment, fragment_offset, block_sizes) =
+            match self.lookup(path)? {
+                Inode::File {
+                    blocks_start,
+                    file_size,
+                    fragment,
+                    fragment_offset,
+                    block_sizes,
+                } => {
+                    (
+                        blocks_start,
+                        file_size,
+                        fragment,
+                        fragment_offset,
+                        block_sizes,
+                    )
+                },
+                _ => return Err(SquashFsErr::NotAFile(path.to_owned())),
+            };
+
+        let mut data = Vec::new();
+        let mut pos = blocks_start;
+
+        for size in block_sizes {
+            let on_disk = (size & 0x00ff_ffff) as usize;
+
+            if on_disk == 0 {
+                // A sparse block.
+                data.resize(data.len() + self.block_size as usize, 0);
+                continue;
+            }
+
+            let block = self.read_at(pos, on_disk)?;
+
+            if size & 0x0100_0000 == 0 {
+                data.extend(self.decompress(&block)?);
+            } else {
+                data.extend(block);
+            }
+
+            pos += on_disk as u64;
+        }
+
+        if fragment != NO_FRAGMENT {
+            // Fragment entries are 16 bytes, 512 to a metadata block.
+            let pointer = self
+                .fragment_table
+                .checked_add((fragment as u64 / 512) * 8)
+                .ok_or(SquashFsErr::InvalidImage)?;
+            let pointer = self.read_at(pointer, 8)?;
+            let entry =
+                self.read_metadata(u64_at(&pointer, 0)?, (fragment as usize % 512) * 16, 16)?;
+            let size = u32_at(&entry, 8)?;
+            let block = self.read_at(u64_at(&entry, 0)?, (size & 0x00ff_ffff) as usize)?;
+            let block = if size & 0x0100_0000 == 0 {
+                self.decompress(&block)?
+            } else {
+                block
+            };
+
+            let start = fragment_offset as usize;
+            let len = (file_size % self.block_size as u64) as usize;
+            data.extend(
+                block
+                    .get(start..(start + len))
+                    .ok_or(SquashFsErr::InvalidImage)?,
+            );
+        }
+
+        data.truncate(file_size as usize);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use ruzstd::encoding::CompressionLevel;
+
+    use super::*;
+
+    /// `data` compressed the way an image with `compression` stores it.
+    fn compress(compression: u16, data: &[u8]) -> Vec<u8> {
+        match compression {
+            COMPRESSION_GZIP => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            },
+            COMPRESSION_XZ => {
+                let mut out = Vec::new();
+                lzma_rs::xz_compress(&mut &data[..], &mut out).unwrap();
+                out
+            },
+            COMPRESSION_ZSTD => ruzstd::encoding::compress_to_vec(data, CompressionLevel::Fastest),
+            _ => data.to_vec(),
+        }
+    }
+
+    /// A metadata block stored uncompressed.
+    fn metadata(data: &[u8]) -> Vec<u8> {
+        let mut block = (data.len() as u16 | 0x8000).to_le_bytes().to_vec();
+        block.extend(data);
+        block
+    }
+
+    fn inode_header(kind: u16, number: u32) -> Vec<u8> {
+        let mut inode = Vec::new();
+        inode.extend(kind.to_le_bytes());
+        inode.extend([0; 10]);
+        inode.extend(number.to_le_bytes());
+        inode
+    }
+
+    fn dir_inode(number: u32, listing_offset: u16, listing_size: usize) -> Vec<u8> {
+        let mut inode = inode_header(1, number);
+        inode.extend(0u32.to_le_bytes());
+        inode.extend(2u32.to_le_bytes());
+        inode.extend((listing_size as u16 + 3).to_le_bytes());
+        inode.extend(listing_offset.to_le_bytes());
+        inode.extend(0u32.to_le_bytes());
+        inode
+    }
+
+    fn listing(entries: &[(&str, u16)]) -> Vec<u8> {
+        let mut listing = Vec::new();
+        listing.extend((entries.len() as u32 - 1).to_le_bytes());
+        listing.extend(0u32.to_le_bytes());
+        listing.extend(1u32.to_le_bytes());
+
+        for (name, inode_offset) in entries {
+            listing.extend(inode_offset.to_le_bytes());
+            listing.extend([0; 4]);
+            listing.extend((name.len() as u16 - 1).to_le_bytes());
+            listing.extend(name.as_bytes());
+        }
+
+        listing
+    }
+
+    /// An image with `app.desktop`, `usr/icon.png` & a `.DirIcon` symlink to the icon, with only
+    /// the desktop file compressed. `prefix` is written before it, like an AppImage's runtime.
+    fn image(name: &str, prefix: &[u8], compression: u16, block_size: u32) -> (File, u64) {
+        let desktop = b"[Desktop Entry]\nName=App\n";
+        let icon = b"\x89PNG";
+        let data_start = 96u64;
+        let mut files = Vec::new();
+        let mut inodes = Vec::new();
+
+        let mut file_inode = |inodes: &mut Vec<u8>, number, contents: &[u8], compressed| {
+            let offset = inodes.len() as u16;
+            let (block, flag) = if compressed {
+                (compress(compression, contents), 0)
+            } else {
+                (contents.to_vec(), 0x0100_0000)
+            };
+            let mut inode = inode_header(2, number);
+            inode.extend(((data_start + files.len() as u64) as u32).to_le_bytes());
+            inode.extend(NO_FRAGMENT.to_le_bytes());
+            inode.extend(0u32.to_le_bytes());
+            inode.extend((contents.len() as u32).to_le_bytes());
+            inode.extend((block.len() as u32 | flag).to_le_bytes());
+            inodes.extend(inode);
+            files.extend(block);
+            offset
+        };
+
+        let desktop_offset = file_inode(&mut inodes, 2, desktop, true);
+        let icon_offset = file_inode(&mut inodes, 3, icon, false);
+
+        let link_offset = inodes.len() as u16;
+        let target = b"usr/icon.png";
+        inodes.extend(inode_header(3, 4));
+        inodes.extend(1u32.to_le_bytes());
+        inodes.extend((target.len() as u32).to_le_bytes());
+        inodes.extend(target);
+
+        let usr_listing = listing(&[("icon.png", icon_offset)]);
+        let usr_offset = inodes.len() as u16;
+        let root_offset = usr_offset + 32;
+        let root_listing = listing(&[
+            (".DirIcon", link_offset),
+            ("app.desktop", desktop_offset),
+            ("usr", usr_offset),
+        ]);
+
+        inodes.extend(dir_inode(5, root_listing.len() as u16, usr_listing.len()));
+        inodes.extend(dir_inode(1, 0, root_listing.len()));
+        // Inodes are always read 56 bytes at a time.
+        inodes.extend([0; 56]);
+
+        let inode_table = data_start + files.len() as u64;
+        let inodes = metadata(&inodes);
+        let directory_table = inode_table + inodes.len() as u64;
+        let mut directories = root_listing;
+        directories.extend(usr_listing);
+        let directories = metadata(&directories);
+
+        let mut superblock = vec![0; 96];
+        superblock[0..4].copy_from_slice(&MAGIC.to_le_bytes());
+        superblock[12..16].copy_from_slice(&block_size.to_le_bytes());
+        superblock[20..22].copy_from_slice(&compression.to_le_bytes());
+        superblock[28..30].copy_from_slice(&4u16.to_le_bytes());
+        superblock[32..40].copy_from_slice(&(root_offset as u64).to_le_bytes());
+        superblock[64..72].copy_from_slice(&inode_table.to_le_bytes());
+        superblock[72..80].copy_from_slice(&directory_table.to_le_bytes());
+
+        let path =
+            std::env::temp_dir().join(format!("pyroxene-squashfs-{}-{}", std::process::id(), name));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(prefix).unwrap();
+        file.write_all(&superblock).unwrap();
+        file.write_all(&files).unwrap();
+        file.write_all(&inodes).unwrap();
+        file.write_all(&directories).unwrap();
+
+        let file = File::open(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        (file, prefix.len() as u64)
+    }
+
+    #[test]
+    fn read_files() {
+        let (file, offset) = image("read", b"runtime", COMPRESSION_GZIP, 4096);
+        let image = SquashFs::open(file, offset).unwrap();
+
+        assert_eq!(
+            image.root_entries().unwrap(),
+            vec![".DirIcon", "app.desktop", "usr"]
+        );
+        assert_eq!(
+            image.read_file("app.desktop").unwrap(),
+            b"[Desktop Entry]\nName=App\n"
+        );
+        assert_eq!(image.read_file("/usr/icon.png").unwrap(), b"\x89PNG");
+        assert_eq!(image.read_file(".DirIcon").unwrap(), b"\x89PNG");
+        assert_eq!(image.read_file("usr/../app.desktop").unwrap().len(), 25);
+        assert!(matches!(
+            image.read_file("usr"),
+            Err(SquashFsErr::NotAFile(_))
+        ));
+        assert!(matches!(
+            image.read_file("usr/missing.png"),
+            Err(SquashFsErr::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn compressions() {
+        for (name, compression) in [("xz", COMPRESSION_XZ), ("zstd", COMPRESSION_ZSTD)] {
+            let (file, offset) = image(name, b"runtime", compression, 128 * 1024);
+            let image = SquashFs::open(file, offset).unwrap();
+
+            assert_eq!(
+                image.read_file("app.desktop").unwrap(),
+                b"[Desktop Entry]\nName=App\n"
+            );
+            assert_eq!(image.read_file(".DirIcon").unwrap(), b"\x89PNG");
+        }
+    }
+
+    #[test]
+    fn invalid_images() {
+        let (file, _) = image("offset", b"runtime", COMPRESSION_GZIP, 4096);
+        assert!(matches!(
+            SquashFs::open(file, 0),
+            Err(SquashFsErr::InvalidImage)
+        ));
+
+        for block_size in [0, 1000, 2048, 2 * 1024 * 1024] {
+            let (file, offset) = image("block-size", b"", COMPRESSION_GZIP, block_size);
+            assert!(matches!(
+                SquashFs::open(file, offset),
+                Err(SquashFsErr::InvalidImage)
+            ));
+        }
+
+        let (file, offset) = image("lzo", b"", 3, 4096);
+        assert!(matches!(
+            SquashFs::open(file, offset),
+            Err(SquashFsErr::UnsupportedCompression(3))
+        ));
+
+        let (file, offset) = image("lz4", b"", 5, 4096);
+        let e = SquashFs::open(file, offset).err().unwrap();
+        assert_eq!(e.to_string(), "unsupported squashfs compression: lz4");
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use crate::desktop::{DesktopEntry, DesktopEntryErr};
+use crate::keyfile::KeyFile;
+use crate::{vdf, xdg};
+
+const RUN_GAME_URL: &str = "steam://rungameid/";
+/// The `StateFlags` bit of a fully installed app.
+const FULLY_INSTALLED: u32 = 4;
+/// Steamworks Common Redistributables, installed alongside most games.
+const REDISTRIBUTABLES: &str = "228980";
+
+/// A game installed in one of the Steam libraries.
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub app_id: String,
+    pub name: String,
+    /// The `appmanifest_<id>.acf` the game was read from.
+    pub manifest: PathBuf,
+}
+
+/// Where Steam may be installed. `~/.steam/steam` is usually a link to the other.
+pub fn root_dirs() -> Vec<PathBuf> {
+    vec![
+        shellexpand::tilde("~/.steam/steam").into_owned().into(),
+        xdg::data_home().join("Steam"),
+    ]
+}
+
+/// The `steamapps` directories of every Steam library, read from `libraryfolders.vdf`.
+pub fn library_dirs() -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut dirs = Vec::new();
+
+    for root in root_dirs() {
+        let steamapps = root.join("steamapps");
+
+        if !steamapps.is_dir() {
+            continue;
+        }
+
+        let mut libraries = vec![steamapps.clone()];
+        let path = steamapps.join("libraryfolders.vdf");
+
+        match vdf::open(&path) {
+            Ok(document) => {
+                let folders = document
+                    .get("libraryfolders")
+                    .map(|folders| folders.pairs())
+                    .unwrap_or(&[]);
+
+                for (key, folder) in folders {
+                    // Older files map the index to the path instead of a block.
+                    let library = match folder.as_str() {
+                        Some(path) if key.parse::<u32>().is_ok() => path,
+                        Some(_) => continue,
+                        None => {
+                            match folder.string("path") {
+                                Some(path) => path,
+                                None => continue,
+                            }
+                        },
+                    };
+
+                    libraries.push(PathBuf::from(library).join("steamapps"));
+                }
+            },
+            Err(e) => {
+                println!(
+                    "Warning: Failed to parse Steam libraries: {:?} ({})",
+                    path, e
+                )
+            },
+        }
+
+        for library in libraries {
+            if let Ok(canonical) = library.canonicalize() {
+                if seen.insert(canonical.clone()) {
+                    dirs.push(canonical);
+                }
+            }
+        }
+    }
+
+    dirs
+}
+
+/// The fully installed games of every library. Proton, the Steam Linux Runtime & the
+/// redistributables are installed like games but can't be launched.
+pub fn installed_games() -> Vec<Game> {
+    let mut seen = HashSet::new();
+    let mut games = Vec::new();
+
+    for library in library_dirs() {
+        let mut manifests: Vec<PathBuf> = match library.read_dir() {
+            Ok(entries) => {
+                entries
+                    .filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .filter(|path| {
+                        path.file_name()
+                            .map(|n| n.to_string_lossy())
+                            .map(|n| n.starts_with("appmanifest_") && n.ends_with(".acf"))
+                            == Some(true)
+                    })
+                    .collect()
+            },
+            Err(_) => continue,
+        };
+
+        manifests.sort();
+
+        for manifest in manifests {
+            let document = match vdf::open(&manifest) {
+                Ok(ok) => ok,
+                Err(e) => {
+                    println!(
+                        "Warning: Failed to parse Steam manifest: {:?} ({})",
+                        manifest, e
+                    );
+                    continue;
+                },
+            };
+
+            let state = match document.get("AppState") {
+                Some(some) => some,
+                None => continue,
+            };
+
+            let (app_id, name) = match (state.string("appid"), state.string("name")) {
+                (Some(app_id), Some(name)) => (app_id.to_owned(), name.to_owned()),
+                _ => continue,
+            };
+
+            let flags = state
+                .string("StateFlags")
+                .and_then(|flags| flags.parse::<u32>().ok())
+                .unwrap_or(0);
+
+            if flags & FULLY_INSTALLED == 0
+                || app_id == REDISTRIBUTABLES
+                || name.starts_with("Proton ")
+                || name.starts_with("Steam Linux Runtime")
+                || !seen.insert(app_id.clone())
+            {
+                continue;
+            }
+
+            games.push(Game {
+                app_id,
+                name,
+                manifest,
+            });
+        }
+    }
+
+    games
+}
+
+/// The Steam app an entry launches, e.g. for the desktop files Steam creates for shortcuts.
+pub fn app_id(entry: &DesktopEntry) -> Option<&str> {
+    let exec = entry.exec.as_deref()?;
+    let start = exec.find(RUN_GAME_URL)? + RUN_GAME_URL.len();
+    let len = exec[start..]
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(exec.len() - start);
+
+    if len == 0 {
+        None
+    } else {
+        Some(&exec[start..(start + len)])
+    }
+}
+
+impl Game {
+    /// An entry in the `Game` category launching the game through Steam.
+    pub fn desktop_entry(&self) -> Result<DesktopEntry, DesktopEntryErr> {
+        let mut key_file = KeyFile::default();
+        let group = key_file.group_or_insert("Desktop Entry");
+        group.set_string("Type", "Application");
+        group.set_string("Name", &self.name);
+        group.set_string("Icon", &format!("steam_icon_{}", self.app_id));
+        group.set_string("Exec", &format!("steam {}{}", RUN_GAME_URL, self.app_id));
+        group.set_list("Categories", &["Game"]);
+        group.set_string("X-Steam-AppId", &self.app_id);
+
+        DesktopEntry::from_key_file(
+            format!("steam-{}.desktop", self.app_id),
+            self.manifest.clone(),
+            key_file,
+            &[],
+        )
+    }
+}
@@ -0,0 +1,414 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::category::{self, MAIN_CATEGORIES, RESERVED_CATEGORIES};
+use crate::discovery;
+use crate::keyfile::{self, Group, KeyFile};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ValueType {
+    String,
+    LocaleString,
+    IconString,
+    Boolean,
+    Strings,
+    LocaleStrings,
+}
+
+const DESKTOP_ENTRY_KEYS: &[(&str, ValueType)] = &[
+    ("Type", ValueType::String),
+    ("Version", ValueType::String),
+    ("Name", ValueType::LocaleString),
+    ("GenericName", ValueType::LocaleString),
+    ("NoDisplay", ValueType::Boolean),
+    ("Comment", ValueType::LocaleString),
+    ("Icon", ValueType::IconString),
+    ("Hidden", ValueType::Boolean),
+    ("OnlyShowIn", ValueType::Strings),
+    ("NotShowIn", ValueType::Strings),
+    ("DBusActivatable", ValueType::Boolean),
+    ("TryExec", ValueType::String),
+    ("Exec", ValueType::String),
+    ("Path", ValueType::String),
+    ("Terminal", ValueType::Boolean),
+    ("Actions", ValueType::Strings),
+    ("MimeType", ValueType::Strings),
+    ("Categories", ValueType::Strings),
+    ("Implements", ValueType::Strings),
+    ("Keywords", ValueType::LocaleStrings),
+    ("StartupNotify", ValueType::Boolean),
+    ("StartupWMClass", ValueType::String),
+    ("URL", ValueType::String),
+    ("PrefersNonDefaultGPU", ValueType::Boolean),
+    ("SingleMainWindow", ValueType::Boolean),
+];
+
+const DESKTOP_ACTION_KEYS: &[(&str, ValueType)] = &[
+    ("Name", ValueType::LocaleString),
+    ("Icon", ValueType::IconString),
+    ("Exec", ValueType::String),
+];
+
+const DEPRECATED_KEYS: &[&str] = &[
+    "Encoding",
+    "MiniIcon",
+    "TerminalOptions",
+    "Protocols",
+    "Extensions",
+    "BinaryPattern",
+    "MapNotify",
+    "SwallowTitle",
+    "SwallowExec",
+    "SortOrder",
+    "FilePattern",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        match self.line {
+            Some(line) => write!(f, "{}: line {}: {}", severity, line, self.message),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+    fn error<M: Into<String>>(&mut self, line: Option<usize>, message: M) {
+        self.0.push(Issue {
+            severity: Severity::Error,
+            line,
+            message: message.into(),
+        });
+    }
+
+    fn warning<M: Into<String>>(&mut self, line: Option<usize>, message: M) {
+        self.0.push(Issue {
+            severity: Severity::Warning,
+            line,
+            message: message.into(),
+        });
+    }
+}
+
+/// Validate each file, or every discovered desktop file if none are given. Issues are printed and
+/// `false` is returned if any file has errors.
+pub fn run(files: &[PathBuf]) -> bool {
+    let files = if files.is_empty() {
+        discovery::desktop_files()
+            .into_iter()
+            .map(|file| file.path)
+            .collect()
+    } else {
+        files.to_vec()
+    };
+
+    let mut error_count = 0;
+    let mut warning_count = 0;
+
+    for file in files {
+        for issue in validate_file(&file) {
+            match issue.severity {
+                Severity::Error => error_count += 1,
+                Severity::Warning => warning_count += 1,
+            }
+
+            println!("{}: {}", file.display(), issue);
+        }
+    }
+
+    println!("{} error(s), {} warning(s)", error_count, warning_count);
+    error_count == 0
+}
+
+pub fn validate_file<P: AsRef<Path>>(path: P) -> Vec<Issue> {
+    let mut issues = Issues::default();
+
+    let key_file = match KeyFile::open(path) {
+        Ok(ok) => ok,
+        Err(e) => {
+            issues.error(None, e.to_string());
+            return issues.0;
+        },
+    };
+
+    let group = match key_file.groups.first() {
+        Some(group) if group.name == "Desktop Entry" => group,
+        Some(group) => {
+            issues.error(Some(group.line), "the first group must be [Desktop Entry]");
+            return issues.0;
+        },
+        None => {
+            issues.error(None, "missing [Desktop Entry] group");
+            return issues.0;
+        },
+    };
+
+    let action_ids = group.list("Actions").unwrap_or_default();
+    let mut group_names = HashSet::new();
+
+    for other in key_file.groups.iter() {
+        if !group_names.insert(other.name.as_str()) {
+            issues.error(
+                Some(other.line),
+                format!("duplicate group [{}]", other.name),
+            );
+        }
+    }
+
+    check_entries(group, DESKTOP_ENTRY_KEYS, &mut issues);
+    check_desktop_entry(group, &mut issues);
+
+    for other in key_file.groups.iter().skip(1) {
+        if let Some(id) = other.name.strip_prefix("Desktop Action ") {
+            if !action_ids.iter().any(|a| a == id) {
+                issues.warning(
+                    Some(other.line),
+                    format!("action \"{}\" is not listed in Actions", id),
+                );
+            }
+
+            check_entries(other, DESKTOP_ACTION_KEYS, &mut issues);
+
+            if other.entry("Name").is_none() {
+                issues.error(
+                    Some(other.line),
+                    format!("action \"{}\" is missing required key Name", id),
+                );
+            }
+
+            match other.string("Exec") {
+                Some(exec) => check_exec(&exec, other.entry("Exec").map(|e| e.line), &mut issues),
+                None => {
+                    if group.boolean("DBusActivatable").ok().flatten() != Some(true) {
+                        issues.error(
+                            Some(other.line),
+                            format!("action \"{}\" is missing required key Exec", id),
+                        );
+                    }
+                },
+            }
+        } else if !other.name.starts_with("X-") {
+            issues.error(
+                Some(other.line),
+                format!(
+                    "unknown group [{}], groups extending the format should start with X-",
+                    other.name
+                ),
+            );
+        }
+    }
+
+    for id in action_ids.iter() {
+        if key_file.group(&format!("Desktop Action {}", id)).is_none() {
+            issues.error(
+                group.entry("Actions").map(|e| e.line),
+                format!("action \"{}\" has no [Desktop Action {}] group", id, id),
+            );
+        }
+    }
+
+    issues.0
+}
+
+fn check_entries(group: &Group, known: &[(&str, ValueType)], issues: &mut Issues) {
+    let mut seen = HashSet::new();
+
+    for entry in group.entries.iter() {
+        let line = Some(entry.line);
+
+        if !entry
+            .key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            issues.error(line, format!("invalid key name {}", entry.key));
+        }
+
+        if !seen.insert((entry.key.as_str(), entry.locale.as_deref())) {
+            issues.error(line, format!("duplicate key {}", entry.key));
+        }
+
+        if entry.key.starts_with("X-") {
+            continue;
+        }
+
+        let value_type = match known.iter().find(|(key, _)| *key == entry.key) {
+            Some((_, value_type)) => *value_type,
+            None => {
+                if DEPRECATED_KEYS.contains(&entry.key.as_str()) {
+                    issues.warning(line, format!("key {} is deprecated", entry.key));
+                } else {
+                    issues.error(
+                        line,
+                        format!(
+                            "unknown key {}, keys extending the format should start with X-",
+                            entry.key
+                        ),
+                    );
+                }
+
+                continue;
+            },
+        };
+
+        let localizable = matches!(
+            value_type,
+            ValueType::LocaleString | ValueType::IconString | ValueType::LocaleStrings
+        );
+
+        if entry.locale.is_some() && !localizable {
+            issues.error(line, format!("key {} can not be localized", entry.key));
+        }
+
+        match value_type {
+            ValueType::Boolean => {
+                if entry.value != "true" && entry.value != "false" {
+                    issues.error(
+                        line,
+                        format!(
+                            "value \"{}\" of {} is not \"true\" or \"false\"",
+                            entry.value, entry.key
+                        ),
+                    );
+                }
+            },
+            ValueType::String | ValueType::LocaleString | ValueType::IconString => {
+                if let Some(escape) = keyfile::invalid_escape(&entry.value, false) {
+                    issues.error(
+                        line,
+                        format!("invalid escape sequence {} in {}", escape, entry.key),
+                    );
+                }
+            },
+            ValueType::Strings | ValueType::LocaleStrings => {
+                if let Some(escape) = keyfile::invalid_escape(&entry.value, true) {
+                    issues.error(
+                        line,
+                        format!("invalid escape sequence {} in {}", escape, entry.key),
+                    );
+                }
+            },
+        }
+    }
+}
+
+fn check_desktop_entry(group: &Group, issues: &mut Issues) {
+    let header = Some(group.line);
+    let dbus_activatable = group.boolean("DBusActivatable").ok().flatten() == Some(true);
+
+    if group.entry("Name").is_none() {
+        issues.error(header, "missing required key Name");
+    }
+
+    match group.raw("Type") {
+        None => issues.error(header, "missing required key Type"),
+        Some("Application") => {
+            match group.string("Exec") {
+                Some(exec) => check_exec(&exec, group.entry("Exec").map(|e| e.line), issues),
+                None => {
+                    if !dbus_activatable {
+                        issues.error(header, "missing required key Exec");
+                    }
+                },
+            }
+        },
+        Some("Link") => {
+            if group.entry("URL").is_none() {
+                issues.error(header, "missing required key URL");
+            }
+        },
+        Some("Directory") => (),
+        Some(other) => {
+            issues.error(
+                group.entry("Type").map(|e| e.line),
+                format!("invalid Type {}", other),
+            );
+        },
+    }
+
+    if let Some(categories) = group.list("Categories") {
+        let line = group.entry("Categories").map(|e| e.line);
+
+        for category in categories.iter() {
+            if RESERVED_CATEGORIES.contains(&category.as_str()) {
+                if group.entry("OnlyShowIn").is_none() {
+                    issues.error(
+                        line,
+                        format!("reserved category {} requires OnlyShowIn", category),
+                    );
+                }
+            } else if let Some(related) = category::related_categories(category) {
+                if !related.is_empty()
+                    && !related
+                        .iter()
+                        .any(|main| categories.contains(&main.to_string()))
+                {
+                    issues.warning(
+                        line,
+                        format!(
+                            "category {} should be used with one of {}",
+                            category,
+                            related.join(", ")
+                        ),
+                    );
+                }
+            } else if !category.starts_with("X-") && !MAIN_CATEGORIES.contains(&category.as_str()) {
+                issues.error(line, format!("unregistered category {}", category));
+            }
+        }
+
+        if categories.iter().any(|c| c == "Audio" || c == "Video")
+            && !categories.iter().any(|c| c == "AudioVideo")
+        {
+            issues.warning(line, "categories Audio & Video require AudioVideo");
+        }
+    }
+}
+
+fn check_exec(exec: &str, line: Option<usize>, issues: &mut Issues) {
+    let mut chars = exec.chars();
+    let mut file_codes = 0;
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+
+        match chars.next() {
+            Some('f') | Some('F') | Some('u') | Some('U') => file_codes += 1,
+            Some('i') | Some('c') | Some('k') | Some('%') => (),
+            Some(code @ 'd') | Some(code @ 'D') | Some(code @ 'n') | Some(code @ 'N')
+            | Some(code @ 'v') | Some(code @ 'm') => {
+                issues.warning(line, format!("field code %{} in Exec is deprecated", code));
+            },
+            Some(code) => issues.error(line, format!("invalid field code %{} in Exec", code)),
+            None => issues.error(line, "Exec ends with an incomplete field code"),
+        }
+    }
+
+    if file_codes > 1 {
+        issues.error(line, "Exec may contain at most one of %f, %F, %u or %U");
+    }
+}
@@ -0,0 +1,266 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::{fmt, io};
+
+/// A value of Valve's KeyValues format, as used by Steam's `.vdf` & `.acf` files.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Block(Vec<(String, Value)>),
+}
+
+#[derive(Debug)]
+pub enum VdfErr {
+    UnterminatedString(usize),
+    UnexpectedBrace(usize),
+    MissingValue(usize, String),
+    UnclosedBlock,
+    IO(io::Error),
+}
+
+impl fmt::Display for VdfErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VdfErr::UnterminatedString(line) => write!(f, "line {}: unterminated string", line),
+            VdfErr::UnexpectedBrace(line) => write!(f, "line {}: unexpected brace", line),
+            VdfErr::MissingValue(line, key) => write!(f, "line {}: {} has no value", line, key),
+            VdfErr::UnclosedBlock => write!(f, "block is never closed"),
+            VdfErr::IO(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for VdfErr {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VdfErr::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            Value::Block(_) => None,
+        }
+    }
+
+    /// The pairs of a block, empty for a string.
+    pub fn pairs(&self) -> &[(String, Value)] {
+        match self {
+            Value::String(_) => &[],
+            Value::Block(pairs) => pairs,
+        }
+    }
+
+    /// The first value of `key` within a block. Keys are case-insensitive.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.pairs()
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn string(&self, key: &str) -> Option<&str> {
+        self.get(key)?.as_str()
+    }
+}
+
+pub fn open<P: AsRef<Path>>(path: P) -> Result<Value, VdfErr> {
+    let mut buffer = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut buffer))
+        .map_err(VdfErr::IO)?;
+    parse(&buffer)
+}
+
+/// Parse a document into a block of its top-level pairs.
+pub fn parse(text: &str) -> Result<Value, VdfErr> {
+    let tokens = tokenize(text)?;
+    let mut stack: Vec<Vec<(String, Value)>> = vec![Vec::new()];
+    let mut tokens = tokens.into_iter();
+
+    while let Some((line, token)) = tokens.next() {
+        match token {
+            Token::Text(key) => {
+                match tokens.next() {
+                    Some((_, Token::Text(value))) => {
+                        stack.last_mut().unwrap().push((key, Value::String(value)))
+                    },
+                    Some((_, Token::Open)) => {
+                        // The key is kept as a placeholder until the block is closed.
+                        stack
+                            .last_mut()
+                            .unwrap()
+                            .push((key, Value::Block(Vec::new())));
+                        stack.push(Vec::new());
+                    },
+                    _ => return Err(VdfErr::MissingValue(line, key)),
+                }
+            },
+            Token::Close => {
+                if stack.len() == 1 {
+                    return Err(VdfErr::UnexpectedBrace(line));
+                }
+
+                let pairs = stack.pop().unwrap();
+
+                if let Some((_, value)) = stack.last_mut().unwrap().last_mut() {
+                    *value = Value::Block(pairs);
+                }
+            },
+            Token::Open => return Err(VdfErr::UnexpectedBrace(line)),
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err(VdfErr::UnclosedBlock);
+    }
+
+    Ok(Value::Block(stack.pop().unwrap()))
+}
+
+/// Split a document into strings & braces with their line numbers. Comments and platform
+/// conditionals like `[$WIN32]` are dropped.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, VdfErr> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => (),
+            '{' => tokens.push((line, Token::Open)),
+            '}' => tokens.push((line, Token::Close)),
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().map(|c| *c != '\n') == Some(true) {
+                    chars.next();
+                }
+            },
+            '[' => {
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+            },
+            '"' => {
+                let start = line;
+                let mut value = String::new();
+
+                loop {
+                    match chars.next() {
+                        None => return Err(VdfErr::UnterminatedString(start)),
+                        Some('"') => break,
+                        Some('\\') => {
+                            match chars.next() {
+                                Some('n') => value.push('\n'),
+                                Some('t') => value.push('\t'),
+                                Some(c) => value.push(c),
+                                None => return Err(VdfErr::UnterminatedString(start)),
+                            }
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+
+                            value.push(c);
+                        },
+                    }
+                }
+
+                tokens.push((start, Token::Text(value)));
+            },
+            c => {
+                let mut value = c.to_string();
+
+                while let Some(c) = chars.peek() {
+                    if c.is_whitespace() || *c == '"' || *c == '{' || *c == '}' {
+                        break;
+                    }
+
+                    value.push(*c);
+                    chars.next();
+                }
+
+                tokens.push((line, Token::Text(value)));
+            },
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_blocks() {
+        let text = r#"
+"libraryfolders"
+{
+    // A comment
+    "0"
+    {
+        "path"      "/home/user/.local/share/Steam"
+        "apps" [$LINUX]
+        {
+            "570"   "123"
+        }
+    }
+    Unquoted    value
+    "escaped"   "a\"b\\c\nd"
+}
+"#;
+        let root = parse(text).unwrap();
+        let folders = root.get("LibraryFolders").unwrap();
+        let library = folders.get("0").unwrap();
+
+        assert_eq!(
+            library.string("path"),
+            Some("/home/user/.local/share/Steam")
+        );
+        assert_eq!(
+            library.get("apps").unwrap().pairs(),
+            &[(String::from("570"), Value::String(String::from("123")))]
+        );
+        assert_eq!(folders.string("unquoted"), Some("value"));
+        assert_eq!(folders.string("escaped"), Some("a\"b\\c\nd"));
+        assert_eq!(folders.string("0"), None);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse("\"a\"\n{\n\"b\" \"c\"\n"),
+            Err(VdfErr::UnclosedBlock)
+        ));
+        assert!(matches!(
+            parse("\"a\" \"b\"\n}"),
+            Err(VdfErr::UnexpectedBrace(2))
+        ));
+        assert!(matches!(parse("{"), Err(VdfErr::UnexpectedBrace(1))));
+        assert!(matches!(
+            parse("\"a\"\n\"b"),
+            Err(VdfErr::UnterminatedString(2))
+        ));
+        assert!(matches!(
+            parse("\"a\" \"b\"\n\"c\""),
+            Err(VdfErr::MissingValue(2, _))
+        ));
+    }
+}
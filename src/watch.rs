@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::discovery;
+
+/// Closing a file after writing is watched instead of every write.
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_ATTRIB
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+/// The size of `struct inotify_event` without its name.
+const EVENT_SIZE: usize = 16;
+/// How long it has to stay quiet before reloading. Package managers write many files at once.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+/// Reload at the latest after this long, even if changes keep coming.
+const MAX_DELAY: Duration = Duration::from_secs(3);
+
+/// A change within a watched directory, or to the directory itself.
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Events were dropped, anything may have changed.
+    pub overflow: bool,
+}
+
+/// Directories watched with inotify.
+pub struct Watcher {
+    fd: libc::c_int,
+    watches: HashMap<libc::c_int, PathBuf>,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Self, io::Error> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Watcher {
+            fd,
+            watches: HashMap::new(),
+        })
+    }
+
+    /// Watch a directory, its subdirectories are not included. Watching it again does nothing.
+    pub fn add(&mut self, dir: &Path) -> Result<(), io::Error> {
+        let path = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), MASK) };
+
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.watches.insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for events, or until there are some if `None`. Nothing is returned
+    /// when it timed out.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<Event>, io::Error> {
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout = match timeout {
+            Some(timeout) => timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+
+        if unsafe { libc::poll(&mut poll_fd, 1, timeout) } < 0 {
+            let e = io::Error::last_os_error();
+
+            return match e.kind() {
+                io::ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(e),
+            };
+        }
+
+        if poll_fd.revents & libc::POLLIN == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut buffer = [0u8; 4096];
+        let len = unsafe {
+            libc::read(
+                self.fd,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let len = len as usize;
+        let mut events = Vec::new();
+        let mut offset = 0;
+
+        while offset + EVENT_SIZE <= len {
+            let field = |at: usize| {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(&buffer[(offset + at)..(offset + at + 4)]);
+                bytes
+            };
+
+            let wd = libc::c_int::from_ne_bytes(field(0));
+            let mask = u32::from_ne_bytes(field(4));
+            let name_len = u32::from_ne_bytes(field(12)) as usize;
+            let start = offset + EVENT_SIZE;
+            let end = (start + name_len).min(len);
+            // The name is padded with nul bytes.
+            let name = &buffer[start..end];
+            let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+            offset = start + name_len;
+
+            if mask & libc::IN_Q_OVERFLOW != 0 {
+                events.push(Event {
+                    path: PathBuf::new(),
+                    is_dir: false,
+                    overflow: true,
+                });
+                continue;
+            }
+
+            if mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&wd);
+                continue;
+            }
+
+            let dir = match self.watches.get(&wd) {
+                Some(some) => some,
+                None => continue,
+            };
+
+            events.push(Event {
+                path: if name.is_empty() {
+                    dir.clone()
+                } else {
+                    dir.join(OsStr::from_bytes(name))
+                },
+                is_dir: name.is_empty() || mask & libc::IN_ISDIR != 0,
+                overflow: false,
+            });
+        }
+
+        Ok(events)
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Watch `trees` with all their subdirectories and `flat_dirs` without theirs, calling
+/// `on_change` with the events once changes to desktop files in `trees`, or to any file in
+/// `flat_dirs`, have settled. Directories that don't exist yet are noticed once they are created.
+/// Only returns if watching fails.
+pub fn run<F: FnMut(Vec<Event>)>(
+    trees: &[PathBuf],
+    flat_dirs: &[PathBuf],
+    mut on_change: F,
+) -> Result<(), io::Error> {
+    let mut watcher = Watcher::new()?;
+
+    loop {
+        // Directories created since the last change are picked up here.
+        for root in trees.iter().chain(flat_dirs) {
+            let dirs = if !root.is_dir() {
+                // Watching the closest existing ancestor notices the root being created.
+                root.ancestors()
+                    .find(|ancestor| ancestor.is_dir())
+                    .map(|ancestor| vec![ancestor.to_path_buf()])
+                    .unwrap_or_default()
+            } else if trees.contains(root) {
+                discovery::walk_dirs(root)
+            } else {
+                vec![root.clone()]
+            };
+
+            for dir in dirs {
+                // Directories can disappear while walking, they are simply not watched.
+                let _ = watcher.add(&dir);
+            }
+        }
+
+        let mut events = Vec::new();
+
+        while events.is_empty() {
+            events = watcher.wait(None)?;
+            events.retain(|event| relevant(event, trees, flat_dirs));
+        }
+
+        let first = Instant::now();
+        let mut last = first;
+
+        while last.elapsed() < DEBOUNCE && first.elapsed() < MAX_DELAY {
+            let timeout = DEBOUNCE.checked_sub(last.elapsed()).unwrap_or_default();
+            let len = events.len();
+
+            events.extend(
+                watcher
+                    .wait(Some(timeout))?
+                    .into_iter()
+                    .filter(|event| relevant(event, trees, flat_dirs)),
+            );
+
+            if events.len() > len {
+                last = Instant::now();
+            }
+        }
+
+        on_change(events);
+    }
+}
+
+/// Whether an event may change the entries. Other files in the application directories and
+/// anything next to the ancestors of roots that don't exist yet are of no interest.
+fn relevant(event: &Event, trees: &[PathBuf], flat_dirs: &[PathBuf]) -> bool {
+    event.overflow
+        || trees
+            .iter()
+            .chain(flat_dirs)
+            .any(|root| root.starts_with(&event.path))
+        || trees.iter().any(|root| {
+            event.path.starts_with(root)
+                && (event.is_dir
+                    || event.path.extension().and_then(|e| e.to_str()) == Some("desktop"))
+        })
+        || flat_dirs
+            .iter()
+            .any(|dir| event.path.parent() == Some(dir.as_path()))
+}
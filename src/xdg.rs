@@ -0,0 +1,51 @@
+use std::env;
+use std::path::PathBuf;
+
+fn home_dir(var: &str, default: &str) -> PathBuf {
+    match env::var_os(var).map(PathBuf::from) {
+        Some(path) if path.is_absolute() => path,
+        _ => shellexpand::tilde(default).into_owned().into(),
+    }
+}
+
+fn dir_list(var: &str, default: &str) -> Vec<PathBuf> {
+    let list = env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| default.into());
+
+    env::split_paths(&list)
+        .filter(|path| path.is_absolute())
+        .collect()
+}
+
+/// `$XDG_DATA_HOME`, defaults to `~/.local/share`.
+pub fn data_home() -> PathBuf {
+    home_dir("XDG_DATA_HOME", "~/.local/share")
+}
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, most important first.
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![data_home()];
+    dirs.append(&mut dir_list(
+        "XDG_DATA_DIRS",
+        "/usr/local/share:/usr/share",
+    ));
+    dirs
+}
+
+/// `$XDG_CONFIG_HOME`, defaults to `~/.config`.
+pub fn config_home() -> PathBuf {
+    home_dir("XDG_CONFIG_HOME", "~/.config")
+}
+
+/// `$XDG_CONFIG_HOME` followed by `$XDG_CONFIG_DIRS`, most important first.
+pub fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![config_home()];
+    dirs.append(&mut dir_list("XDG_CONFIG_DIRS", "/etc/xdg"));
+    dirs
+}
+
+/// `$XDG_CACHE_HOME`, defaults to `~/.cache`.
+pub fn cache_home() -> PathBuf {
+    home_dir("XDG_CACHE_HOME", "~/.cache")
+}